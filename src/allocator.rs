@@ -0,0 +1,188 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use spin::Mutex;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+/// Virtual address the heap is mapped at; chosen to be easy to spot in faults.
+pub const HEAP_START: usize = 0x_4444_4444_0000;
+pub const HEAP_SIZE: usize = 100 * 1024;
+
+#[global_allocator]
+static ALLOCATOR: BumpAllocator = BumpAllocator::new();
+
+/// Maps the heap region and hands it to the global allocator.
+///
+/// Must run once, before the first allocation; until then every allocation
+/// fails.
+pub fn init_heap(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let heap_start = VirtAddr::new(HEAP_START as u64);
+    let heap_end = heap_start + (HEAP_SIZE - 1) as u64;
+    let pages = Page::range_inclusive(
+        Page::containing_address(heap_start),
+        Page::containing_address(heap_end),
+    );
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    for page in pages {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        // SAFETY: The heap region is reserved for this purpose and not mapped
+        // yet, and each frame is freshly allocated.
+        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    }
+
+    ALLOCATOR.init(HEAP_START, HEAP_SIZE);
+    Ok(())
+}
+
+/// Hands out memory by moving a pointer forward through the heap.
+///
+/// Individual frees only decrement a counter; the whole heap becomes
+/// reusable once every allocation has been freed. Simple and fast, but long
+/// lived allocations pin everything allocated after them.
+///
+/// TODO: Replace with a free-list allocator so freed blocks can be reused
+/// individually.
+pub struct BumpAllocator {
+    state: Mutex<BumpState>,
+}
+
+struct BumpState {
+    heap_start: usize,
+    heap_end: usize,
+    /// Start of the unused part of the heap.
+    next: usize,
+    /// Number of live allocations.
+    allocations: usize,
+}
+
+impl BumpAllocator {
+    /// Creates an allocator with an empty heap; see `init`.
+    pub const fn new() -> BumpAllocator {
+        BumpAllocator {
+            state: Mutex::new(BumpState {
+                heap_start: 0,
+                heap_end: 0,
+                next: 0,
+                allocations: 0,
+            }),
+        }
+    }
+
+    /// Gives the allocator the `size`-byte region starting at `start`.
+    ///
+    /// The region must be mapped and unused; `init_heap` takes care of that.
+    fn init(&self, start: usize, size: usize) {
+        let mut state = self.state.lock();
+        state.heap_start = start;
+        state.heap_end = start + size;
+        state.next = start;
+    }
+}
+
+impl Default for BumpAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: Every returned block lies inside the heap region, is aligned as
+// requested and does not overlap any live block, because `next` only moves
+// forward until all blocks have been freed.
+unsafe impl GlobalAlloc for BumpAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut state = self.state.lock();
+
+        // Alignments are powers of two, so rounding up is a mask
+        let start = match state.next.checked_add(layout.align() - 1) {
+            Some(addr) => addr & !(layout.align() - 1),
+            None => return ptr::null_mut(),
+        };
+        let end = match start.checked_add(layout.size()) {
+            Some(end) if end <= state.heap_end => end,
+            _ => return ptr::null_mut(),
+        };
+
+        state.next = end;
+        state.allocations += 1;
+        start as *mut u8
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+        let mut state = self.state.lock();
+        state.allocations -= 1;
+        if state.allocations == 0 {
+            state.next = state.heap_start;
+        }
+    }
+}
+
+#[test_case]
+fn test_heap_allocations() {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    let boxed = Box::new(41);
+    assert_eq!(*boxed + 1, 42);
+
+    let mut numbers = Vec::new();
+    for i in 0..500u64 {
+        numbers.push(i);
+    }
+    assert_eq!(numbers.iter().sum::<u64>(), 499 * 500 / 2);
+    drop(numbers);
+    drop(boxed);
+
+    // Far more bytes than the heap holds in total, so this only succeeds if
+    // freed memory is reused
+    for i in 0..HEAP_SIZE {
+        let value = Box::new(i);
+        assert_eq!(*value, i);
+    }
+}
+
+#[test_case]
+fn test_allocation_throughput() {
+    use crate::serial_println;
+    use alloc::alloc::{alloc, dealloc};
+    use core::arch::x86_64::_rdtsc;
+
+    // Mixed small sizes, each batch freed before the next so the heap never
+    // runs out (and a bump allocator gets to reset)
+    const SIZES: [usize; 8] = [8, 16, 24, 32, 64, 128, 256, 512];
+    const BATCH: usize = 32;
+    const ROUNDS: usize = 1000;
+    let mut blocks = [(ptr::null_mut(), Layout::new::<u8>()); BATCH];
+
+    // SAFETY: RDTSC is available on every x86_64 CPU and has no side effects.
+    let start = unsafe { _rdtsc() };
+    for round in 0..ROUNDS {
+        for (i, block) in blocks.iter_mut().enumerate() {
+            let size = SIZES[(round + i) % SIZES.len()];
+            let layout = Layout::from_size_align(size, 8).expect("valid layout");
+            // SAFETY: The layout has a non-zero size.
+            let ptr = unsafe { alloc(layout) };
+            assert!(!ptr.is_null());
+            *block = (ptr, layout);
+        }
+        for &(ptr, layout) in &blocks {
+            // SAFETY: Each block was just allocated with this layout and is
+            // freed once.
+            unsafe { dealloc(ptr, layout) };
+        }
+    }
+    let cycles = unsafe { _rdtsc() } - start;
+
+    let allocations = (ROUNDS * BATCH) as u64;
+    serial_println!(
+        "{} allocations: {} cycles per allocation and free",
+        allocations,
+        cycles / allocations
+    );
+}
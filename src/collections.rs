@@ -0,0 +1,208 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Fixed-capacity, lock-free single-producer/single-consumer ring queue.
+///
+/// Intended for passing events (scancodes, serial bytes, mouse packets) from
+/// an interrupt handler to regular kernel code. It never allocates, never
+/// blocks and never takes a lock, so it is safe to push from interrupt
+/// context even if the consumer was interrupted mid-`pop`.
+///
+/// When the queue is full, `push` drops the new element and increments a
+/// counter readable through `dropped()`; older elements are never
+/// overwritten.
+///
+/// # Concurrency
+///
+/// Exactly one context may push and exactly one context may pop at any time
+/// (e.g. an ISR producing and the main loop consuming). The head and tail
+/// indices each have a single writer, which is what makes the queue lock-free.
+/// Calling `push` from two contexts concurrently (or `pop` from two)
+/// would race on the same slot and must be avoided.
+pub struct ArrayQueue<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    /// Index of the next element to pop. Only written by the consumer.
+    head: AtomicUsize,
+    /// Index of the next free slot. Only written by the producer.
+    tail: AtomicUsize,
+    /// Number of elements rejected because the queue was full.
+    dropped: AtomicUsize,
+}
+
+// SAFETY: Elements are moved in by the producer and out by the consumer, so
+// `T: Send` is sufficient. Slot access is coordinated by the Release/Acquire
+// pairs on `head` and `tail`: a slot is only read after the producer has
+// published it, and only reused after the consumer has released it.
+unsafe impl<T: Send, const N: usize> Sync for ArrayQueue<T, N> {}
+
+impl<T, const N: usize> ArrayQueue<T, N> {
+    /// Creates an empty queue. Usable in `static` initializers.
+    pub const fn new() -> Self {
+        assert!(N > 0, "ArrayQueue capacity must be > 0");
+        ArrayQueue {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Appends `value`, or returns it back if the queue is full.
+    ///
+    /// A full queue also increments the `dropped()` counter.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        // Indices grow monotonically and wrap around usize; the slot is the
+        // index modulo N, and tail - head is always the current length.
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) >= N {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return Err(value);
+        }
+
+        // SAFETY: The slot at `tail` is outside the consumer's [head, tail)
+        // window, so nobody else is reading or writing it.
+        unsafe {
+            (*self.slots[tail % N].get()).write(value);
+        }
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Removes and returns the oldest element, or `None` if the queue is empty.
+    pub fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        // SAFETY: `head != tail`, so the slot at `head` was initialized by the
+        // producer, and the Acquire load of `tail` makes that write visible.
+        // Advancing `head` afterwards hands the slot back to the producer.
+        let value = unsafe { (*self.slots[head % N].get()).assume_init_read() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    /// Number of elements currently queued.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        // Load head first: it never passes tail, so a consumer popping in
+        // between can only make the result too large, never negative
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(N)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of elements the queue can hold.
+    #[allow(dead_code)]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Number of elements dropped so far because the queue was full.
+    #[allow(dead_code)]
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T, const N: usize> Default for ArrayQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayQueue<T, N> {
+    fn drop(&mut self) {
+        // Drop any elements still in the queue
+        while self.pop().is_some() {}
+    }
+}
+
+#[test_case]
+fn test_array_queue_fifo_order() {
+    let queue: ArrayQueue<u32, 8> = ArrayQueue::new();
+    assert!(queue.is_empty());
+    assert_eq!(queue.pop(), None);
+
+    for i in 0..5 {
+        assert_eq!(queue.push(i), Ok(()));
+    }
+    assert_eq!(queue.len(), 5);
+    for i in 0..5 {
+        assert_eq!(queue.pop(), Some(i));
+    }
+    assert_eq!(queue.pop(), None);
+}
+
+#[test_case]
+fn test_array_queue_wraparound() {
+    let queue: ArrayQueue<u32, 4> = ArrayQueue::new();
+
+    // Many more elements than slots, so the indices wrap around repeatedly
+    for i in 0..100 {
+        assert_eq!(queue.push(i), Ok(()));
+        assert_eq!(queue.push(i + 1000), Ok(()));
+        assert_eq!(queue.pop(), Some(i));
+        assert_eq!(queue.pop(), Some(i + 1000));
+    }
+    assert!(queue.is_empty());
+    assert_eq!(queue.dropped(), 0);
+}
+
+#[test_case]
+fn test_array_queue_full_returns_item() {
+    let queue: ArrayQueue<u32, 3> = ArrayQueue::new();
+    for i in 0..3 {
+        assert_eq!(queue.push(i), Ok(()));
+    }
+
+    assert_eq!(queue.push(7), Err(7));
+    assert_eq!(queue.push(8), Err(8));
+    assert_eq!(queue.dropped(), 2);
+    assert_eq!(queue.len(), queue.capacity());
+
+    // The queued elements are untouched and space frees up again
+    assert_eq!(queue.pop(), Some(0));
+    assert_eq!(queue.push(9), Ok(()));
+    assert_eq!(queue.pop(), Some(1));
+    assert_eq!(queue.pop(), Some(2));
+    assert_eq!(queue.pop(), Some(9));
+    assert_eq!(queue.pop(), None);
+}
+
+#[test_case]
+fn test_array_queue_interleaved() {
+    let queue: ArrayQueue<u32, 8> = ArrayQueue::new();
+    let mut next_push = 0;
+    let mut next_pop = 0;
+
+    // Uneven bursts on each side, as an ISR and the main loop would produce
+    for round in 0..50 {
+        for _ in 0..(round % 5) {
+            if queue.push(next_push).is_ok() {
+                next_push += 1;
+            }
+        }
+        for _ in 0..(round % 3) {
+            if let Some(value) = queue.pop() {
+                assert_eq!(value, next_pop);
+                next_pop += 1;
+            }
+        }
+        assert_eq!(queue.len(), (next_push - next_pop) as usize);
+    }
+    while let Some(value) = queue.pop() {
+        assert_eq!(value, next_pop);
+        next_pop += 1;
+    }
+    assert_eq!(next_pop, next_push);
+}
@@ -0,0 +1,64 @@
+use core::ptr::addr_of;
+use spin::Lazy;
+use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::tss::TaskStateSegment;
+use x86_64::VirtAddr;
+
+/// IST slot holding the double-fault stack.
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+
+/// Size of the double-fault stack. There is no guard page below it, so a
+/// handler that overflows this stack corrupts whatever precedes it.
+const DOUBLE_FAULT_STACK_SIZE: usize = 4096 * 5;
+
+/// Task state segment; in long mode it only supplies the IST stacks.
+static TSS: Lazy<TaskStateSegment> = Lazy::new(|| {
+    let mut tss = TaskStateSegment::new();
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
+        // `static mut` places the stack in writable memory; it is only ever
+        // used by the CPU, never accessed from Rust.
+        static mut STACK: [u8; DOUBLE_FAULT_STACK_SIZE] = [0; DOUBLE_FAULT_STACK_SIZE];
+
+        // Stacks grow downward, so the IST entry is the end address
+        // Only the address is taken; no reference to STACK is created
+        let stack_start = VirtAddr::from_ptr(addr_of!(STACK));
+        stack_start + DOUBLE_FAULT_STACK_SIZE as u64
+    };
+    tss
+});
+
+struct Selectors {
+    code_selector: SegmentSelector,
+    tss_selector: SegmentSelector,
+}
+
+/// Global descriptor table with a kernel code segment and the TSS.
+static GDT: Lazy<(GlobalDescriptorTable, Selectors)> = Lazy::new(|| {
+    let mut gdt = GlobalDescriptorTable::new();
+    let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+    let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
+    (
+        gdt,
+        Selectors {
+            code_selector,
+            tss_selector,
+        },
+    )
+});
+
+/// Loads the GDT, reloads `CS` and loads the TSS.
+///
+/// Must run before `interrupts::init_idt`, whose double-fault entry refers to
+/// the IST stack defined here.
+pub fn init() {
+    use x86_64::instructions::segmentation::{Segment, CS};
+    use x86_64::instructions::tables::load_tss;
+
+    GDT.0.load();
+    // SAFETY: The selectors point at the code segment and TSS descriptors of
+    // the GDT that was just loaded, which lives in a static and so stays valid.
+    unsafe {
+        CS::set_reg(GDT.1.code_selector);
+        load_tss(GDT.1.tss_selector);
+    }
+}
@@ -0,0 +1,443 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Lazy;
+use x86_64::registers::control::{Cr0, Cr0Flags};
+use x86_64::VirtAddr;
+#[cfg(test)]
+use core::sync::atomic::AtomicBool;
+#[cfg(test)]
+use x86_64::registers::rflags::{self, RFlags};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+use crate::pic::{self, InterruptIndex};
+use crate::{gdt, keyboard, println, timer};
+
+/// The interrupt descriptor table, built on first use by `init_idt`.
+///
+/// Every entry is an interrupt gate, which clears RFLAGS.IF on entry, except
+/// for the exceptions raised deliberately by an instruction (`int3`, `into`,
+/// `bound`). Those are trap gates and leave IF as it was, so the timer keeps
+/// running while they are handled. Hardware interrupt handlers rely on IF
+/// being clear: they are never nested, and never interrupted by code that
+/// could take a lock they hold.
+static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
+    let mut idt = InterruptDescriptorTable::new();
+    idt.breakpoint.set_handler_fn(breakpoint_handler).disable_interrupts(false);
+    idt.overflow.set_handler_fn(overflow_handler).disable_interrupts(false);
+    idt.bound_range_exceeded
+        .set_handler_fn(bound_range_exceeded_handler)
+        .disable_interrupts(false);
+    idt.device_not_available.set_handler_fn(device_not_available_handler);
+    idt.x87_floating_point.set_handler_fn(x87_floating_point_handler);
+    idt.alignment_check.set_handler_fn(alignment_check_handler);
+    idt.machine_check.set_handler_fn(machine_check_handler);
+    idt.simd_floating_point.set_handler_fn(simd_floating_point_handler);
+    idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
+    idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+    // SAFETY: `syscall_entry` is a complete interrupt handler: it saves every
+    // register it touches except RAX, which carries the result, and returns
+    // with `iretq`.
+    unsafe {
+        idt[SYSCALL_VECTOR].set_handler_addr(VirtAddr::from_ptr(syscall_entry as *const ()));
+    }
+    // SAFETY: The IST index refers to the double-fault stack set up by
+    // `gdt::init`, which is not used by any other handler.
+    unsafe {
+        idt.double_fault
+            .set_handler_fn(double_fault_handler)
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+    }
+    idt
+});
+
+/// Loads the IDT so CPU exceptions reach our handlers instead of
+/// triple-faulting. Call `gdt::init` first.
+pub fn init_idt() {
+    IDT.load();
+}
+
+/// Returns whether maskable interrupts are enabled (RFLAGS.IF is set).
+///
+/// Code that takes a lock an interrupt handler might also take must run
+/// with interrupts disabled; `print!`, `serial_print!` and the logger see to
+/// that themselves.
+// Diagnostics for callers; nothing in the kernel checks them yet
+#[allow(dead_code)]
+pub fn interrupts_enabled() -> bool {
+    x86_64::instructions::interrupts::are_enabled()
+}
+
+/// Vector of the `int 0x80` system call gate.
+const SYSCALL_VECTOR: usize = 0x80;
+
+/// Prints `arg2` bytes of UTF-8 starting at address `arg1`; returns the
+/// number of bytes written.
+pub const SYS_WRITE: u64 = 1;
+
+/// Returned for an unknown system call or invalid arguments.
+pub const SYSCALL_ERROR: u64 = u64::MAX;
+
+/// Makes system call `number` through `int 0x80`.
+///
+/// The number goes in RAX and the arguments in RDI, RSI and RDX; the result
+/// comes back in RAX and every other register is preserved. The gate is only
+/// open to ring 0 for now.
+///
+/// # Safety
+///
+/// The arguments must be valid for the system call, e.g. a readable buffer
+/// for `SYS_WRITE`. Like `print!`, `SYS_WRITE` must not be made while the
+/// `WRITER` lock is held.
+// No user mode yet, so only the tests make system calls
+#[allow(dead_code)]
+pub unsafe fn syscall(number: u64, arg1: u64, arg2: u64, arg3: u64) -> u64 {
+    let result;
+    core::arch::asm!(
+        "int 0x80",
+        inlateout("rax") number => result,
+        in("rdi") arg1,
+        in("rsi") arg2,
+        in("rdx") arg3,
+    );
+    result
+}
+
+/// Entry point of the `int 0x80` gate.
+///
+/// Saves the registers the System V ABI lets `dispatch_syscall` clobber,
+/// moves the number and arguments into argument registers, and returns the
+/// result in RAX. The CPU aligns the stack to 16 bytes before pushing its
+/// five-word frame, so one extra word restores the alignment calls expect.
+#[unsafe(naked)]
+extern "C" fn syscall_entry() {
+    core::arch::naked_asm!(
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "mov rcx, rdx",
+        "mov rdx, rsi",
+        "mov rsi, rdi",
+        "mov rdi, rax",
+        "sub rsp, 8",
+        "call {dispatch}",
+        "add rsp, 8",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "iretq",
+        dispatch = sym dispatch_syscall,
+    );
+}
+
+/// Runs system call `number`; called by `syscall_entry` with interrupts
+/// disabled.
+extern "C" fn dispatch_syscall(number: u64, arg1: u64, arg2: u64, _arg3: u64) -> u64 {
+    match number {
+        SYS_WRITE => {
+            // SAFETY: `syscall`'s contract makes the caller pass a readable
+            // buffer of `arg2` bytes.
+            let bytes = unsafe { core::slice::from_raw_parts(arg1 as *const u8, arg2 as usize) };
+            match core::str::from_utf8(bytes) {
+                Ok(text) => {
+                    crate::print!("{}", text);
+                    arg2
+                }
+                Err(_) => SYSCALL_ERROR,
+            }
+        }
+        _ => SYSCALL_ERROR,
+    }
+}
+
+/// Reports an `int3` and resumes execution after it.
+///
+/// Prints through `WRITER`, so a breakpoint hit while the writer is locked
+/// would deadlock; breakpoints are only raised deliberately for now.
+extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
+    #[cfg(test)]
+    BREAKPOINT_RFLAGS.store(rflags::read_raw(), Ordering::Relaxed);
+    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+}
+
+/// RFLAGS seen inside the latest timer and breakpoint handlers, so tests can
+/// check the gate types.
+#[cfg(test)]
+static TIMER_RFLAGS: AtomicU64 = AtomicU64::new(0);
+#[cfg(test)]
+static BREAKPOINT_RFLAGS: AtomicU64 = AtomicU64::new(0);
+
+/// While set, the timer handler prints a dot with `try_print!` on every tick
+/// and counts whether it got through, to measure contention on `WRITER`.
+#[cfg(test)]
+static TIMER_PRINTS: AtomicBool = AtomicBool::new(false);
+#[cfg(test)]
+static TIMER_PRINTED: AtomicU64 = AtomicU64::new(0);
+#[cfg(test)]
+static TIMER_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Number of #NM exceptions resolved by clearing CR0.TS.
+static FPU_TRAPS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns how many times an FPU instruction trapped because CR0.TS was set
+/// and was then allowed to run.
+#[allow(dead_code)]
+pub fn fpu_traps() -> u64 {
+    FPU_TRAPS.load(Ordering::Relaxed)
+}
+
+/// Hardware interrupts received on each of the 16 PIC lines since boot.
+static IRQ_COUNTS: [AtomicU64; 16] = [const { AtomicU64::new(0) }; 16];
+
+/// Returns how many interrupts each IRQ line has delivered since boot,
+/// indexed by IRQ number.
+///
+/// Only lines with a handler are counted; masked lines always read 0.
+#[allow(dead_code)]
+pub fn stats() -> [u64; 16] {
+    core::array::from_fn(|irq| IRQ_COUNTS[irq].load(Ordering::Relaxed))
+}
+
+/// Counts one interrupt on the line behind `index`. Called first thing in
+/// every hardware interrupt handler, so it must stay a single atomic add.
+fn count_irq(index: InterruptIndex) {
+    let irq = usize::from(index.as_u8() - pic::PIC_1_OFFSET);
+    IRQ_COUNTS[irq].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Handles `into` executed with the overflow flag set.
+extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
+    panic!("EXCEPTION: OVERFLOW\n{:#?}", stack_frame);
+}
+
+/// Handles a `bound` instruction whose index was out of range.
+extern "x86-interrupt" fn bound_range_exceeded_handler(stack_frame: InterruptStackFrame) {
+    panic!("EXCEPTION: BOUND RANGE EXCEEDED\n{:#?}", stack_frame);
+}
+
+/// Handles an FPU or SIMD instruction executed while the FPU is unavailable.
+///
+/// If CR0.TS is set, the instruction only trapped so a task switch could
+/// save the FPU state lazily. There are no tasks yet, so the flag is cleared,
+/// counted in `fpu_traps` and the instruction retried. Otherwise the FPU is
+/// missing or disabled (CR0.EM), which cannot be recovered from.
+extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
+    if Cr0::read().contains(Cr0Flags::TASK_SWITCHED) {
+        // SAFETY: Clearing TS only re-enables the FPU; no other CR0 bit
+        // changes.
+        unsafe {
+            Cr0::update(|flags| flags.remove(Cr0Flags::TASK_SWITCHED));
+        }
+        FPU_TRAPS.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    panic!("EXCEPTION: DEVICE NOT AVAILABLE\n{:#?}", stack_frame);
+}
+
+/// Handles a pending unmasked x87 floating-point error.
+extern "x86-interrupt" fn x87_floating_point_handler(stack_frame: InterruptStackFrame) {
+    panic!("EXCEPTION: x87 FLOATING POINT\n{:#?}", stack_frame);
+}
+
+/// Handles an unaligned access while alignment checking is enabled.
+///
+/// Checking is only active in ring 3 with CR0.AM and RFLAGS.AC set, so this
+/// should never fire in the kernel.
+extern "x86-interrupt" fn alignment_check_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    panic!("EXCEPTION: ALIGNMENT CHECK (error code {:#x})\n{:#?}", error_code, stack_frame);
+}
+
+/// Handles a hardware error reported by the CPU's machine-check
+/// architecture.
+///
+/// The machine state is no longer trustworthy, so this cannot return.
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    panic!("EXCEPTION: MACHINE CHECK\n{:#?}", stack_frame);
+}
+
+/// Handles an unmasked SSE floating-point exception.
+extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: InterruptStackFrame) {
+    panic!("EXCEPTION: SIMD FLOATING POINT\n{:#?}", stack_frame);
+}
+
+/// Handles an exception raised while another exception was being delivered.
+///
+/// A double fault cannot be resumed, so this panics. It runs on its own IST
+/// stack, so even a kernel stack overflow reaches it instead of ending in a
+/// triple fault.
+extern "x86-interrupt" fn double_fault_handler(
+    stack_frame: InterruptStackFrame,
+    _error_code: u64,
+) -> ! {
+    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+}
+
+/// Counts a PIT tick and acknowledges the interrupt.
+///
+/// Must not take any lock the interrupted code might hold, so it only
+/// touches the atomic counters and the PICs.
+extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    count_irq(InterruptIndex::Timer);
+    #[cfg(test)]
+    {
+        TIMER_RFLAGS.store(rflags::read_raw(), Ordering::Relaxed);
+        if TIMER_PRINTS.load(Ordering::Relaxed) {
+            let counter = if crate::try_print!(".") { &TIMER_PRINTED } else { &TIMER_DROPPED };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    timer::tick();
+    pic::notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
+}
+
+/// Queues the pending scancode and acknowledges the interrupt.
+///
+/// Decoding happens later in `keyboard::read_key`, outside interrupt context.
+extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    count_irq(InterruptIndex::Keyboard);
+    keyboard::handle_interrupt();
+    pic::notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
+}
+
+#[test_case]
+fn test_interrupts_enabled() {
+    use x86_64::instructions::interrupts;
+
+    // The test runner runs with interrupts on, as `kernel_main` leaves them
+    assert!(interrupts_enabled());
+    interrupts::disable();
+    assert!(!interrupts_enabled());
+    interrupts::enable();
+    assert!(interrupts_enabled());
+
+    // The print paths restore the previous state
+    interrupts::without_interrupts(|| {
+        assert!(!interrupts_enabled());
+        crate::serial_print!("");
+        assert!(!interrupts_enabled());
+    });
+    crate::print!("");
+    assert!(interrupts_enabled());
+}
+
+#[test_case]
+fn test_breakpoint_exception() {
+    // The handler returns, so execution continues after the int3
+    x86_64::instructions::interrupts::int3();
+}
+
+#[test_case]
+fn test_gate_types() {
+    let interrupt_flag = RFlags::INTERRUPT_FLAG.bits();
+
+    // The timer arrives through an interrupt gate, so IF is clear inside it
+    timer::sleep_ticks(2);
+    assert_eq!(TIMER_RFLAGS.load(Ordering::Relaxed) & interrupt_flag, 0);
+
+    // The breakpoint is a trap gate and keeps IF set
+    x86_64::instructions::interrupts::int3();
+    assert_ne!(BREAKPOINT_RFLAGS.load(Ordering::Relaxed) & interrupt_flag, 0);
+}
+
+#[test_case]
+fn test_syscall_write() {
+    use crate::vga_buffer::{BUFFER_HEIGHT, WRITER};
+
+    let last = BUFFER_HEIGHT - 1;
+    WRITER.lock().set_cursor_position(last, 0);
+    let text = "via int 0x80";
+    // SAFETY: The buffer is valid for its length, and WRITER is not locked.
+    let written = unsafe { syscall(SYS_WRITE, text.as_ptr() as u64, text.len() as u64, 0) };
+    assert_eq!(written, text.len() as u64);
+
+    let writer = WRITER.lock();
+    for (col, byte) in text.bytes().enumerate() {
+        assert_eq!(writer.read_cell(last, col).map(|(b, _)| b), Some(byte));
+    }
+    drop(writer);
+
+    // Unknown numbers and invalid UTF-8 are rejected
+    // SAFETY: No buffer is read for an unknown number; the second one is a
+    // valid two-byte buffer.
+    unsafe {
+        assert_eq!(syscall(0xdead, 0, 0, 0), SYSCALL_ERROR);
+        let invalid = [0xff_u8, 0xfe];
+        assert_eq!(syscall(SYS_WRITE, invalid.as_ptr() as u64, 2, 0), SYSCALL_ERROR);
+    }
+}
+
+#[test_case]
+fn test_writer_contention() {
+    use crate::serial_println;
+    use crate::vga_buffer::WRITER;
+    use core::arch::x86_64::_rdtsc;
+    use core::fmt::Write;
+
+    const TICKS: u64 = 20;
+
+    // The main loop holds WRITER with interrupts on, as code that locks it
+    // directly does, while the timer handler tries to print on every tick
+    let printed = TIMER_PRINTED.load(Ordering::Relaxed);
+    let dropped = TIMER_DROPPED.load(Ordering::Relaxed);
+    TIMER_PRINTS.store(true, Ordering::Relaxed);
+    let end = timer::ticks() + TICKS;
+    let (mut writes, mut held) = (0u64, 0u64);
+    while timer::ticks() < end {
+        let mut writer = WRITER.lock();
+        // SAFETY: RDTSC is available on every x86_64 CPU and has no side
+        // effects.
+        let locked = unsafe { _rdtsc() };
+        let _ = writeln!(writer, "contention {}", writes);
+        held += unsafe { _rdtsc() } - locked;
+        drop(writer);
+        writes += 1;
+    }
+    TIMER_PRINTS.store(false, Ordering::Relaxed);
+    let printed = TIMER_PRINTED.load(Ordering::Relaxed) - printed;
+    let dropped = TIMER_DROPPED.load(Ordering::Relaxed) - dropped;
+
+    serial_println!(
+        "{} ticks: {} writes holding WRITER {} cycles on average, {} ISR prints, {} dropped",
+        TICKS,
+        writes,
+        held / writes,
+        printed,
+        dropped
+    );
+    // Every tick while the flag was set either printed or was dropped
+    assert!(printed + dropped >= TICKS);
+}
+
+#[test_case]
+fn test_device_not_available_exception() {
+    let before = fpu_traps();
+    // SAFETY: Setting TS only makes the next FPU instruction trap; the #NM
+    // handler clears it again and the instruction is retried.
+    unsafe {
+        Cr0::update(|flags| flags.insert(Cr0Flags::TASK_SWITCHED));
+        core::arch::asm!("fnop", options(nomem, nostack));
+    }
+    assert_eq!(fpu_traps(), before + 1);
+    assert!(!Cr0::read().contains(Cr0Flags::TASK_SWITCHED));
+}
+
+#[test_case]
+fn test_irq_stats() {
+    let before = stats();
+    timer::sleep_ticks(3);
+    let after = stats();
+    assert!(after[0] >= before[0] + 3);
+    // IRQ 7 is masked, so it never fires
+    assert_eq!(after[7], 0);
+}
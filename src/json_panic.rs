@@ -0,0 +1,92 @@
+//! Single-line JSON panic reports for CI.
+//!
+//! With the `json-panic` cargo feature, the panic handler also writes one
+//! line such as
+//! `{"panic":true,"file":"src/main.rs","line":12,"col":5,"msg":"..."}` to
+//! the serial port, so a test harness can parse the failure instead of
+//! scraping the free-form text. Everything is formatted through `core::fmt`
+//! on the stack; nothing is allocated.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+/// `fmt::Write` adapter that escapes everything written through it for use
+/// inside a JSON string literal.
+///
+/// Quotes, backslashes and control characters are escaped; everything else,
+/// including non-ASCII text, passes through unchanged.
+pub struct JsonEscaper<W>(pub W);
+
+impl<W: Write> Write for JsonEscaper<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '"' => self.0.write_str("\\\"")?,
+                '\\' => self.0.write_str("\\\\")?,
+                '\n' => self.0.write_str("\\n")?,
+                '\r' => self.0.write_str("\\r")?,
+                '\t' => self.0.write_str("\\t")?,
+                c if c < ' ' => write!(self.0, "\\u{:04x}", u32::from(c))?,
+                c => self.0.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `info` to `out` as one JSON object followed by a newline.
+///
+/// A panic without a location reports `null` for the file, line and column.
+// Only the non-test panic handler calls this, and only with `json-panic`
+#[cfg_attr(any(test, not(feature = "json-panic")), allow(dead_code))]
+pub fn write_panic_json<W: Write>(out: &mut W, info: &PanicInfo) -> fmt::Result {
+    out.write_str("{\"panic\":true,")?;
+    match info.location() {
+        Some(location) => write!(
+            out,
+            "\"file\":\"{}\",\"line\":{},\"col\":{},",
+            Escaped(location.file()),
+            location.line(),
+            location.column()
+        )?,
+        None => out.write_str("\"file\":null,\"line\":null,\"col\":null,")?,
+    }
+    write!(out, "\"msg\":\"")?;
+    write!(JsonEscaper(&mut *out), "{}", info.message())?;
+    out.write_str("\"}\n")
+}
+
+/// Displays a string JSON-escaped, for use inside `write!`.
+struct Escaped<'a>(&'a str);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        JsonEscaper(f).write_str(self.0)
+    }
+}
+
+#[test_case]
+fn test_json_escaper() {
+    /// Collects output in a fixed stack buffer.
+    struct Buffer {
+        bytes: [u8; 64],
+        len: usize,
+    }
+
+    impl Write for Buffer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.bytes.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    let mut buffer = Buffer {
+        bytes: [0; 64],
+        len: 0,
+    };
+    let word = "hi";
+    let _ = write!(JsonEscaper(&mut buffer), "say \"{}\"\\\n\t\u{1}é", word);
+    assert_eq!(&buffer.bytes[..buffer.len], r#"say \"hi\"\\\n\t\u0001é"#.as_bytes());
+}
@@ -0,0 +1,384 @@
+//! Interrupt-driven PS/2 keyboard input.
+//!
+//! The keyboard interrupt handler reads each scancode from the PS/2
+//! controller and queues it without decoding; `read_key` and `read_line`
+//! later translate the queued scancodes from scancode set 1 (the set the
+//! controller presents by default) to ASCII for a US layout. Between key
+//! presses the CPU halts instead of polling.
+//!
+//! A held character key repeats after `set_repeat`'s delay and rate, timed by
+//! the tick counter; the keyboard's own typematic repeats are ignored, so the
+//! repeat rate is the same on every machine.
+//!
+//! To try it out, call `read_line` from `kernel_main`, start the kernel with
+//! `make run` and type into the QEMU window (it needs keyboard focus; click it
+//! first). Under `cargo test` QEMU runs with `-display none`, so no keys ever
+//! arrive and only the translation table and the queue are tested.
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::PortReadOnly;
+
+use crate::collections::ArrayQueue;
+use crate::timer;
+use crate::vga_buffer::WRITER;
+
+/// PS/2 controller data port; holds the next scancode.
+const DATA_PORT: u16 = 0x60;
+/// Scancodes buffered between the interrupt handler and `read_key`.
+const SCANCODE_QUEUE_SIZE: usize = 128;
+
+/// Break codes are the make code with bit 7 set.
+const BREAK_BIT: u8 = 0x80;
+/// Prefix byte for the extended (cursor block, right Ctrl/Alt, ...) keys.
+const EXTENDED_PREFIX: u8 = 0xe0;
+
+/// Default time a key must be held before it starts repeating.
+const DEFAULT_REPEAT_DELAY_MS: u64 = 500;
+/// Default time between repeats of a held key.
+const DEFAULT_REPEAT_RATE_MS: u64 = 100;
+
+const LEFT_SHIFT: u8 = 0x2a;
+const RIGHT_SHIFT: u8 = 0x36;
+const CAPS_LOCK: u8 = 0x3a;
+
+/// ASCII produced by each set-1 make code without Shift; 0 means no character.
+const NORMAL: [u8; 0x3a] = [
+    0, 0x1b, b'1', b'2', b'3', b'4', b'5', b'6', // 0x00-0x07
+    b'7', b'8', b'9', b'0', b'-', b'=', 0x08, b'\t', // 0x08-0x0f
+    b'q', b'w', b'e', b'r', b't', b'y', b'u', b'i', // 0x10-0x17
+    b'o', b'p', b'[', b']', b'\n', 0, b'a', b's', // 0x18-0x1f
+    b'd', b'f', b'g', b'h', b'j', b'k', b'l', b';', // 0x20-0x27
+    b'\'', b'`', 0, b'\\', b'z', b'x', b'c', b'v', // 0x28-0x2f
+    b'b', b'n', b'm', b',', b'.', b'/', 0, b'*', // 0x30-0x37
+    0, b' ', // 0x38-0x39
+];
+
+/// ASCII produced by each set-1 make code with Shift held.
+const SHIFTED: [u8; 0x3a] = [
+    0, 0x1b, b'!', b'@', b'#', b'$', b'%', b'^', // 0x00-0x07
+    b'&', b'*', b'(', b')', b'_', b'+', 0x08, b'\t', // 0x08-0x0f
+    b'Q', b'W', b'E', b'R', b'T', b'Y', b'U', b'I', // 0x10-0x17
+    b'O', b'P', b'{', b'}', b'\n', 0, b'A', b'S', // 0x18-0x1f
+    b'D', b'F', b'G', b'H', b'J', b'K', b'L', b':', // 0x20-0x27
+    b'"', b'~', 0, b'|', b'Z', b'X', b'C', b'V', // 0x28-0x2f
+    b'B', b'N', b'M', b'<', b'>', b'?', 0, b'*', // 0x30-0x37
+    0, b' ', // 0x38-0x39
+];
+
+/// A character key being held down.
+struct HeldKey {
+    /// Make code of the key.
+    code: u8,
+    /// The byte it produced when pressed, which every repeat produces too.
+    ascii: u8,
+    /// Uptime at which it repeats next.
+    next_ms: u64,
+}
+
+/// Modifier and auto-repeat state needed to turn scancodes into characters.
+pub struct Keyboard {
+    shift: bool,
+    caps_lock: bool,
+    /// The previous byte was `EXTENDED_PREFIX`.
+    extended: bool,
+    held: Option<HeldKey>,
+    repeat_delay_ms: u64,
+    repeat_rate_ms: u64,
+}
+
+impl Keyboard {
+    pub const fn new() -> Keyboard {
+        Keyboard {
+            shift: false,
+            caps_lock: false,
+            extended: false,
+            held: None,
+            repeat_delay_ms: DEFAULT_REPEAT_DELAY_MS,
+            repeat_rate_ms: DEFAULT_REPEAT_RATE_MS,
+        }
+    }
+
+    /// Sets how long a key must be held before it repeats, and the time
+    /// between repeats after that. A rate of 0 is treated as 1 ms.
+    pub fn set_repeat(&mut self, delay_ms: u64, rate_ms: u64) {
+        self.repeat_delay_ms = delay_ms;
+        self.repeat_rate_ms = rate_ms.max(1);
+    }
+
+    /// Feeds one scancode received now; see `process_at`.
+    pub fn process(&mut self, scancode: u8) -> Option<u8> {
+        self.process_at(scancode, timer::uptime_ms())
+    }
+
+    /// Feeds one scancode received at uptime `now_ms` and returns the ASCII
+    /// byte it produces, if any.
+    ///
+    /// Shift and Caps Lock are tracked here; Caps Lock only affects letters,
+    /// and Shift inverts it. Key releases, other modifiers and extended keys
+    /// produce nothing. Enter yields `\n` and Backspace yields 0x08.
+    ///
+    /// A key producing a byte is held until it is released or another one is
+    /// pressed, and `poll_repeat` repeats it. Further make codes for the held
+    /// key are the keyboard's own typematic repeats and produce nothing.
+    pub fn process_at(&mut self, scancode: u8, now_ms: u64) -> Option<u8> {
+        if scancode == EXTENDED_PREFIX {
+            self.extended = true;
+            return None;
+        }
+        if self.extended {
+            // None of the extended keys map to ASCII
+            self.extended = false;
+            return None;
+        }
+
+        let released = scancode & BREAK_BIT != 0;
+        match scancode & !BREAK_BIT {
+            LEFT_SHIFT | RIGHT_SHIFT => {
+                self.shift = !released;
+                None
+            }
+            CAPS_LOCK => {
+                if !released {
+                    self.caps_lock = !self.caps_lock;
+                }
+                None
+            }
+            code if released => {
+                if self.held.as_ref().is_some_and(|held| held.code == code) {
+                    self.held = None;
+                }
+                None
+            }
+            code => {
+                if self.held.as_ref().is_some_and(|held| held.code == code) {
+                    return None;
+                }
+                let normal = *NORMAL.get(code as usize)?;
+                let shifted = if normal.is_ascii_lowercase() {
+                    self.shift != self.caps_lock
+                } else {
+                    self.shift
+                };
+                let ascii = if shifted { SHIFTED[code as usize] } else { normal };
+                if ascii == 0 {
+                    return None;
+                }
+                self.held = Some(HeldKey {
+                    code,
+                    ascii,
+                    next_ms: now_ms + self.repeat_delay_ms,
+                });
+                Some(ascii)
+            }
+        }
+    }
+
+    /// Returns the held key's byte again if it is due to repeat at uptime
+    /// `now_ms`.
+    ///
+    /// Call this periodically, at least once per repeat interval. Repeats
+    /// missed because it was called late are skipped rather than delivered
+    /// in a burst.
+    pub fn poll_repeat(&mut self, now_ms: u64) -> Option<u8> {
+        let rate = self.repeat_rate_ms;
+        let held = self.held.as_mut()?;
+        if now_ms < held.next_ms {
+            return None;
+        }
+        held.next_ms += rate;
+        if held.next_ms <= now_ms {
+            held.next_ms = now_ms + rate;
+        }
+        Some(held.ascii)
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Modifier state for the PS/2 keyboard.
+static KEYBOARD: Mutex<Keyboard> = Mutex::new(Keyboard::new());
+
+/// Raw scancodes pushed by the interrupt handler, oldest first.
+static SCANCODES: ArrayQueue<u8, SCANCODE_QUEUE_SIZE> = ArrayQueue::new();
+
+/// Reads the pending scancode into the queue. Called by the keyboard
+/// interrupt handler.
+///
+/// Never blocks or allocates. If the queue is full the scancode is dropped.
+pub fn handle_interrupt() {
+    let mut data: PortReadOnly<u8> = PortReadOnly::new(DATA_PORT);
+    // SAFETY: 0x60 is the PS/2 controller data port, and IRQ 1 means it holds
+    // a byte. Reading it acknowledges the byte to the controller.
+    let scancode = unsafe { data.read() };
+    let _ = SCANCODES.push(scancode);
+}
+
+/// Sets the auto-repeat delay and rate of the PS/2 keyboard; see
+/// `Keyboard::set_repeat`. Defaults to 500 ms and 100 ms.
+// Driver API for callers; kernel_main does not read the keyboard yet
+#[allow(dead_code)]
+pub fn set_repeat(delay_ms: u64, rate_ms: u64) {
+    KEYBOARD.lock().set_repeat(delay_ms, rate_ms);
+}
+
+/// Returns the oldest queued scancode, or `None` if no key event is pending.
+///
+/// The queue has a single consumer: only one context may pop at a time.
+#[allow(dead_code)]
+pub fn pop_scancode() -> Option<u8> {
+    SCANCODES.pop()
+}
+
+/// Halts until a key producing a character is pressed, or a held one
+/// repeats, and returns it.
+///
+/// Interrupts must be enabled; otherwise this never returns. Timer
+/// interrupts wake the CPU to check for repeats.
+#[allow(dead_code)]
+pub fn read_key() -> u8 {
+    loop {
+        // Check the queue with interrupts off, so a key arriving between the
+        // check and the `hlt` still wakes us
+        interrupts::disable();
+        match pop_scancode() {
+            Some(scancode) => {
+                interrupts::enable();
+                if let Some(ascii) = KEYBOARD.lock().process(scancode) {
+                    return ascii;
+                }
+            }
+            None => {
+                if let Some(ascii) = KEYBOARD.lock().poll_repeat(timer::uptime_ms()) {
+                    interrupts::enable();
+                    return ascii;
+                }
+                interrupts::enable_and_hlt();
+            }
+        }
+    }
+}
+
+/// Reads a line of input into `buf`, echoing it to the screen.
+///
+/// Blocks until Enter is pressed and returns the line without the newline.
+/// Backspace erases the last character. Printable ASCII is accepted until
+/// `buf` is full; further characters, and keys such as Tab and Esc, are
+/// ignored.
+#[allow(dead_code)]
+pub fn read_line(buf: &mut [u8]) -> &str {
+    let mut len = 0;
+    loop {
+        match read_key() {
+            b'\n' => {
+                WRITER.lock().write_byte(b'\n');
+                break;
+            }
+            0x08 => {
+                if len > 0 {
+                    len -= 1;
+                    WRITER.lock().write_byte(0x08);
+                }
+            }
+            byte @ 0x20..=0x7e if len < buf.len() => {
+                buf[len] = byte;
+                len += 1;
+                WRITER.lock().write_byte(byte);
+            }
+            _ => {}
+        }
+    }
+    // Only printable ASCII was stored, so this never fails
+    core::str::from_utf8(&buf[..len]).unwrap_or("")
+}
+
+#[test_case]
+fn test_scancode_translation() {
+    let mut keyboard = Keyboard::new();
+    assert_eq!(keyboard.process(0x1e), Some(b'a'));
+    assert_eq!(keyboard.process(0x9e), None);
+    assert_eq!(keyboard.process(0x02), Some(b'1'));
+    assert_eq!(keyboard.process(0x1c), Some(b'\n'));
+    assert_eq!(keyboard.process(0x0e), Some(0x08));
+    assert_eq!(keyboard.process(0x39), Some(b' '));
+    assert_eq!(keyboard.process(0x1d), None);
+
+    // Shift affects letters and symbols
+    keyboard.process(LEFT_SHIFT);
+    assert_eq!(keyboard.process(0x1e), Some(b'A'));
+    assert_eq!(keyboard.process(0x02), Some(b'!'));
+    keyboard.process(LEFT_SHIFT | BREAK_BIT);
+
+    // Caps Lock affects letters only, and Shift inverts it
+    keyboard.process(CAPS_LOCK);
+    assert_eq!(keyboard.process(0x1e), Some(b'A'));
+    assert_eq!(keyboard.process(0x02), Some(b'1'));
+    keyboard.process(RIGHT_SHIFT);
+    assert_eq!(keyboard.process(0x1e), Some(b'a'));
+    keyboard.process(0x9e);
+    keyboard.process(RIGHT_SHIFT | BREAK_BIT);
+
+    // Extended keys are swallowed along with their prefix
+    assert_eq!(keyboard.process(EXTENDED_PREFIX), None);
+    assert_eq!(keyboard.process(0x1c), None);
+    assert_eq!(keyboard.process(0x1e), Some(b'A'));
+}
+
+#[test_case]
+fn test_auto_repeat() {
+    let mut keyboard = Keyboard::new();
+    keyboard.set_repeat(500, 100);
+    assert_eq!(keyboard.process_at(0x1e, 0), Some(b'a'));
+    // The keyboard's own typematic repeats are ignored
+    assert_eq!(keyboard.process_at(0x1e, 250), None);
+
+    // Polled every tick for a second: repeats at 500, 600, ..., 1000 ms
+    let repeats = (0..=1000)
+        .step_by(10)
+        .filter(|&now| keyboard.poll_repeat(now) == Some(b'a'))
+        .count();
+    assert_eq!(repeats, 6);
+
+    // A late poll yields a single repeat, not a burst
+    assert_eq!(keyboard.poll_repeat(1550), Some(b'a'));
+    assert_eq!(keyboard.poll_repeat(1560), None);
+
+    // Releasing a key stops it; releasing another key does not
+    assert_eq!(keyboard.process_at(0x30, 2000), Some(b'b'));
+    keyboard.process_at(0x9e, 2000);
+    assert_eq!(keyboard.poll_repeat(2500), Some(b'b'));
+    keyboard.process_at(0xb0, 2500);
+    assert_eq!(keyboard.poll_repeat(5000), None);
+}
+
+#[test_case]
+fn test_scancode_queue_burst() {
+    // A separate queue, so no real keyboard interrupt can interfere
+    let queue: ArrayQueue<u8, SCANCODE_QUEUE_SIZE> = ArrayQueue::new();
+
+    // A burst larger than the queue keeps the oldest scancodes
+    for i in 0..200u8 {
+        let _ = queue.push(i);
+    }
+    assert_eq!(queue.len(), SCANCODE_QUEUE_SIZE);
+    assert_eq!(queue.dropped(), 200 - SCANCODE_QUEUE_SIZE);
+    for i in 0..SCANCODE_QUEUE_SIZE {
+        assert_eq!(queue.pop(), Some(i as u8));
+    }
+    assert_eq!(queue.pop(), None);
+
+    // Short bursts drained in between never drop anything
+    for burst in 0..10u8 {
+        for i in 0..16 {
+            assert_eq!(queue.push(burst * 16 + i), Ok(()));
+        }
+        for i in 0..16 {
+            assert_eq!(queue.pop(), Some(burst * 16 + i));
+        }
+    }
+    assert_eq!(queue.dropped(), 200 - SCANCODE_QUEUE_SIZE);
+}
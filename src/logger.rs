@@ -0,0 +1,110 @@
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU8, Ordering};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::serial::SERIAL1;
+use crate::vga_buffer::{Color, WRITER};
+
+/// Most verbose level still logged, stored as a `LevelFilter` discriminant.
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(LevelFilter::Info as u8);
+
+static LOGGER: KernelLogger = KernelLogger;
+
+/// Sends `log` records to both the screen and the serial port.
+///
+/// Each line starts with the level in brackets; on screen the tag is colored
+/// by level (errors red, warnings yellow, and so on) while the message keeps
+/// the writer's current color.
+struct KernelLogger;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let level = record.level();
+        // As in `print!`, no interrupt handler may run while a lock is held
+        without_interrupts(|| {
+            let mut writer = WRITER.lock();
+            let previous = writer.color_code().foreground();
+            writer.set_foreground(level_color(level));
+            let _ = write!(writer, "[{}]", level);
+            writer.set_foreground(previous);
+            let _ = writeln!(writer, " {}", record.args());
+        });
+        without_interrupts(|| {
+            let _ = writeln!(SERIAL1.lock(), "[{}] {}", level, record.args());
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Color of the level tag on screen.
+fn level_color(level: Level) -> Color {
+    match level {
+        Level::Error => Color::LightRed,
+        Level::Warn => Color::Yellow,
+        Level::Info => Color::LightGreen,
+        Level::Debug => Color::LightCyan,
+        Level::Trace => Color::DarkGray,
+    }
+}
+
+/// Installs the kernel logger. The initial level is `Info`.
+///
+/// Safe to call more than once; later calls do nothing.
+pub fn init_logging() {
+    // Only fails if a logger is already set, and only this one ever is
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(max_level());
+    }
+}
+
+/// Changes the most verbose level that is logged, at runtime.
+// For callers that want quieter or noisier logs at runtime
+#[allow(dead_code)]
+pub fn set_max_level(level: LevelFilter) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+    // Lets the log macros skip disabled records before calling the logger
+    log::set_max_level(level);
+}
+
+/// Returns the most verbose level currently logged.
+pub fn max_level() -> LevelFilter {
+    match MAX_LEVEL.load(Ordering::Relaxed) {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+#[test_case]
+fn test_level_filtering() {
+    init_logging();
+    set_max_level(LevelFilter::Warn);
+    WRITER.lock().clear_screen();
+
+    log::info!("hidden");
+    log::error!("shown");
+    set_max_level(LevelFilter::Info);
+
+    let writer = WRITER.lock();
+    for (col, byte) in b"[ERROR] shown".iter().enumerate() {
+        assert_eq!(writer.read_cell(0, col).map(|(b, _)| b), Some(*byte));
+    }
+    assert_eq!(writer.read_cell(1, 0).map(|(b, _)| b), Some(b' '));
+    assert_eq!(
+        writer.read_cell(0, 1).map(|(_, color)| color.foreground()),
+        Some(Color::LightRed)
+    );
+}
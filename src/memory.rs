@@ -0,0 +1,608 @@
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::arch::asm;
+use core::fmt;
+use core::ops::Range;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags, PhysFrame,
+    Size2MiB, Size4KiB,
+};
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::{PhysAddr, VirtAddr};
+
+/// Page-table mapper and frame allocator, stored once `kernel_main` has set
+/// them up so other code (and tests) can map memory.
+pub struct Memory {
+    pub mapper: OffsetPageTable<'static>,
+    pub frame_allocator: BootInfoFrameAllocator,
+}
+
+/// Global memory state; `None` until `kernel_main` fills it in.
+pub static MEMORY: Mutex<Option<Memory>> = Mutex::new(None);
+
+impl Memory {
+    /// Maps `size` bytes of fresh memory at `start` with `flags`.
+    ///
+    /// Uses 2 MiB pages wherever a whole 2 MiB-aligned page fits in the range
+    /// and 4 KiB pages for the unaligned edges, which saves page-table memory
+    /// and TLB entries for large regions. The range is widened to 4 KiB page
+    /// boundaries. Nothing in the range may be mapped yet. On error, the pages
+    /// mapped so far stay mapped.
+    // Mapping API for callers; the heap is the only mapping the kernel makes
+    #[allow(dead_code)]
+    pub fn map_huge_region(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: PageTableFlags,
+    ) -> Result<(), MapToError<Size4KiB>> {
+        let mut addr = start.align_down(Size4KiB::SIZE);
+        let end = (start + size as u64).align_up(Size4KiB::SIZE);
+        while addr < end {
+            if addr.is_aligned(Size2MiB::SIZE) && end - addr >= Size2MiB::SIZE {
+                let page = Page::<Size2MiB>::containing_address(addr);
+                let frame = FrameAllocator::<Size2MiB>::allocate_frame(&mut self.frame_allocator)
+                    .ok_or(MapToError::FrameAllocationFailed)?;
+                let flags = flags | PageTableFlags::HUGE_PAGE;
+                // SAFETY: The page is unmapped and the frame freshly allocated,
+                // so the mapping aliases nothing.
+                unsafe { self.mapper.map_to(page, frame, flags, &mut self.frame_allocator) }
+                    .map_err(huge_map_error)?
+                    .flush();
+                addr += Size2MiB::SIZE;
+            } else {
+                let page = Page::<Size4KiB>::containing_address(addr);
+                let frame = FrameAllocator::<Size4KiB>::allocate_frame(&mut self.frame_allocator)
+                    .ok_or(MapToError::FrameAllocationFailed)?;
+                // SAFETY: As above.
+                unsafe { self.mapper.map_to(page, frame, flags, &mut self.frame_allocator)? }
+                    .flush();
+                addr += Size4KiB::SIZE;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Converts an error from mapping a 2 MiB page to the 4 KiB error type
+/// `map_huge_region` returns.
+fn huge_map_error(error: MapToError<Size2MiB>) -> MapToError<Size4KiB> {
+    match error {
+        MapToError::FrameAllocationFailed => MapToError::FrameAllocationFailed,
+        MapToError::ParentEntryHugePage => MapToError::ParentEntryHugePage,
+        MapToError::PageAlreadyMapped(frame) => {
+            MapToError::PageAlreadyMapped(PhysFrame::containing_address(frame.start_address()))
+        }
+    }
+}
+
+/// Physical address used to detect a disabled A20 line (the boot sector
+/// signature), and its alias with address bit 20 set.
+const A20_LOW: u64 = 0x7dfe;
+const A20_HIGH: u64 = A20_LOW | 1 << 20;
+
+/// Creates a mapper for the active page tables.
+///
+/// Also checks that the A20 line is enabled and logs a warning if it is not;
+/// the bootloader should always have enabled it, but if it did not, every
+/// odd megabyte of physical memory aliases the even one below it.
+///
+/// # Safety
+///
+/// The bootloader must have mapped all of physical memory at
+/// `physical_memory_offset`. This must be called only once, since each call
+/// hands out a `&'static mut` reference to the active level 4 table.
+pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    if !a20_enabled(physical_memory_offset) {
+        log::warn!("A20 line is disabled: physical memory above 1 MiB wraps around");
+    }
+    let level_4_table = active_level_4_table(physical_memory_offset);
+    OffsetPageTable::new(level_4_table, physical_memory_offset)
+}
+
+/// Returns whether the A20 line is enabled, i.e. whether a physical address
+/// with bit 20 set reaches different memory than the same address without it.
+///
+/// Writes a sentinel to `A20_HIGH` and checks that it does not show up at
+/// `A20_LOW`, then restores the original value. If A20 is off the two are
+/// the same memory, so the restore puts back both.
+///
+/// # Safety
+///
+/// All of physical memory must be mapped at `physical_memory_offset`, and no
+/// other CPU may access the two addresses meanwhile.
+unsafe fn a20_enabled(physical_memory_offset: VirtAddr) -> bool {
+    let low: *mut u16 = (physical_memory_offset + A20_LOW).as_mut_ptr();
+    let high: *mut u16 = (physical_memory_offset + A20_HIGH).as_mut_ptr();
+    // No interrupt handler may see the sentinel
+    without_interrupts(|| {
+        let saved = high.read_volatile();
+        let sentinel = !low.read_volatile();
+        high.write_volatile(sentinel);
+        let wrapped = low.read_volatile() == sentinel;
+        high.write_volatile(saved);
+        !wrapped
+    })
+}
+
+/// Returns the active level 4 table through the physical memory mapping.
+///
+/// # Safety
+///
+/// Same requirements as `init`.
+unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
+    let (level_4_frame, _) = Cr3::read();
+    let virt = physical_memory_offset + level_4_frame.start_address().as_u64();
+    &mut *virt.as_mut_ptr()
+}
+
+/// Flags reported by `mappings`. Accessed and dirty bits change as memory is
+/// used, so they are left out and do not split ranges.
+const MAPPING_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::USER_ACCESSIBLE)
+    .union(PageTableFlags::NO_EXECUTE);
+
+/// A virtual range mapped to contiguous physical memory with one page size
+/// and one set of flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    pub virt: VirtAddr,
+    pub phys: PhysAddr,
+    /// Length of the range in bytes.
+    pub len: u64,
+    /// 4 KiB, 2 MiB or 1 GiB.
+    pub page_size: u64,
+    /// Only the flags in `MAPPING_FLAGS`.
+    pub flags: PageTableFlags,
+}
+
+/// Walks the active page tables and calls `f` for each mapped range, in
+/// order of virtual address.
+///
+/// Neighbouring pages are merged into one range when both their virtual and
+/// physical addresses follow on and their page size and flags match.
+/// `mapper` must be the one `init` returned; only its physical memory offset
+/// is used.
+// A debugging aid; nothing in the kernel dumps its page tables by default
+#[allow(dead_code)]
+pub fn mappings(mapper: &OffsetPageTable, mut f: impl FnMut(Mapping)) {
+    let offset = mapper.phys_offset();
+    let (level_4_frame, _) = Cr3::read();
+    // SAFETY: CR3 holds the active level 4 table, and `init` guarantees all
+    // physical memory is mapped at `offset`.
+    let level_4 = unsafe { table_at(offset, level_4_frame.start_address()) };
+
+    let mut run: Option<Mapping> = None;
+    walk(offset, level_4, 4, 0, &mut |virt, phys, page_size, flags| {
+        if let Some(run) = run.as_mut() {
+            let end = run.virt.as_u64().wrapping_add(run.len);
+            if end == virt
+                && run.phys.as_u64() + run.len == phys.as_u64()
+                && run.page_size == page_size
+                && run.flags == flags
+            {
+                run.len += page_size;
+                return;
+            }
+        }
+        let next = Mapping {
+            virt: VirtAddr::new_truncate(virt),
+            phys,
+            len: page_size,
+            page_size,
+            flags,
+        };
+        if let Some(done) = run.replace(next) {
+            f(done);
+        }
+    });
+    if let Some(done) = run {
+        f(done);
+    }
+}
+
+/// Prints the active page tables as a table of mapped ranges; see
+/// `mappings`.
+///
+/// Each row shows the virtual range (end exclusive), its physical start, the
+/// page size and the flags: `P`resent, `W`ritable, `U`ser accessible and
+/// e`X`ecutable, or `-` where a flag is clear.
+#[allow(dead_code)]
+pub fn dump_mappings(mapper: &OffsetPageTable, out: &mut impl fmt::Write) -> fmt::Result {
+    writeln!(out, "{:<33} {:<13} page flags", "virtual", "physical")?;
+    let mut result = Ok(());
+    mappings(mapper, |mapping| {
+        if result.is_err() {
+            return;
+        }
+        let size = match mapping.page_size {
+            0x1000 => "4K",
+            0x20_0000 => "2M",
+            _ => "1G",
+        };
+        let flag = |flag, c| if mapping.flags.contains(flag) { c } else { '-' };
+        let executable = if mapping.flags.contains(PageTableFlags::NO_EXECUTE) { '-' } else { 'X' };
+        result = writeln!(
+            out,
+            "{:016x}-{:016x} {:013x} {:>4} {}{}{}{}",
+            mapping.virt.as_u64(),
+            mapping.virt.as_u64().wrapping_add(mapping.len),
+            mapping.phys.as_u64(),
+            size,
+            flag(PageTableFlags::PRESENT, 'P'),
+            flag(PageTableFlags::WRITABLE, 'W'),
+            flag(PageTableFlags::USER_ACCESSIBLE, 'U'),
+            executable
+        );
+    });
+    result
+}
+
+/// Calls `out` with the virtual address, physical address, page size and
+/// flags of every page mapped by `table`, a table at `level` (4 down to 1)
+/// covering the virtual addresses from `base`.
+fn walk(
+    offset: VirtAddr,
+    table: &PageTable,
+    level: u8,
+    base: u64,
+    out: &mut dyn FnMut(u64, PhysAddr, u64, PageTableFlags),
+) {
+    let shift = 12 + 9 * u64::from(level - 1);
+    for (index, entry) in table.iter().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        let virt = base | (index as u64) << shift;
+        // Level 1 entries map 4 KiB pages; huge pages end the walk early at
+        // levels 2 (2 MiB) and 3 (1 GiB)
+        if level == 1 || (level < 4 && flags.contains(PageTableFlags::HUGE_PAGE)) {
+            out(virt, entry.addr(), 1 << shift, flags & MAPPING_FLAGS);
+        } else {
+            // SAFETY: A present entry above level 1 without the huge page
+            // flag points to the next-level table.
+            let next = unsafe { table_at(offset, entry.addr()) };
+            walk(offset, next, level - 1, virt, out);
+        }
+    }
+}
+
+/// Returns the page table at physical address `addr`.
+///
+/// # Safety
+///
+/// A page table must be at `addr`, and all physical memory must be mapped
+/// at `offset`.
+unsafe fn table_at(offset: VirtAddr, addr: PhysAddr) -> &'static PageTable {
+    &*(offset + addr.as_u64()).as_ptr()
+}
+
+/// Frame pointer of `kernel_main`, the outermost frame `stack_trace` walks;
+/// 0 until `record_stack_top` runs.
+static STACK_TOP: AtomicU64 = AtomicU64::new(0);
+
+/// Most frames `stack_trace` prints.
+const MAX_FRAMES: usize = 32;
+
+/// Returns the frame pointer of the function this is inlined into.
+#[inline(always)]
+fn frame_pointer() -> u64 {
+    let rbp: u64;
+    // SAFETY: Copying RBP into a register has no side effects.
+    unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+    rbp
+}
+
+/// Records the calling function's frame as the outermost one `stack_trace`
+/// may read. `kernel_main` calls this first thing.
+#[inline(always)]
+pub fn record_stack_top() {
+    STACK_TOP.store(frame_pointer(), Ordering::Relaxed);
+}
+
+/// Prints the return addresses of the calling functions, innermost first, by
+/// following the saved frame pointers.
+///
+/// Best effort: it relies on every function keeping RBP as a frame pointer,
+/// which `.cargo/config.toml` enables. The walk stops after `MAX_FRAMES`, or
+/// at a frame pointer that is null, misaligned, not above the previous one
+/// or above `kernel_main`'s frame, so it only ever reads the stack between
+/// here and `kernel_main`. Before `record_stack_top` runs nothing is printed.
+/// Addresses are not symbolized; look them up with `addr2line`.
+pub fn stack_trace(out: &mut impl fmt::Write) -> fmt::Result {
+    let top = STACK_TOP.load(Ordering::Relaxed);
+    let mut rbp = frame_pointer();
+    writeln!(out, "stack trace:")?;
+    for _ in 0..MAX_FRAMES {
+        if rbp == 0 || !rbp.is_multiple_of(8) || rbp > top {
+            break;
+        }
+        let frame = rbp as *const u64;
+        // SAFETY: `rbp` lies between the current frame and `kernel_main`'s, so
+        // it points at a saved frame pointer followed by a return address on
+        // the mapped stack.
+        let (next, return_address) = unsafe { (frame.read(), frame.add(1).read()) };
+        if return_address == 0 {
+            break;
+        }
+        writeln!(out, "  {:#018x}", return_address)?;
+        if next <= rbp {
+            break;
+        }
+        rbp = next;
+    }
+    Ok(())
+}
+
+/// Hands out the usable frames of the bootloader's memory map, in order of
+/// physical address.
+///
+/// Both 4 KiB and 2 MiB frames are available. Frames are never reused; there
+/// is no deallocation yet, and the 4 KiB frames skipped to reach the next
+/// 2 MiB boundary are lost. Frames inside the kernel image are skipped even if
+/// the map wrongly marks them usable.
+pub struct BootInfoFrameAllocator {
+    memory_map: &'static MemoryMap,
+    /// Physical addresses of the loaded kernel image.
+    kernel: Range<u64>,
+    /// Physical address below which no frame is handed out any more.
+    next: u64,
+}
+
+impl BootInfoFrameAllocator {
+    /// Creates an allocator over the regions the memory map marks usable.
+    ///
+    /// # Safety
+    ///
+    /// Every region marked `Usable` in `memory_map` must really be unused;
+    /// otherwise the same memory could be handed out twice.
+    pub unsafe fn init(memory_map: &'static MemoryMap) -> BootInfoFrameAllocator {
+        let kernel = kernel_range(memory_map);
+        let overlaps = memory_map.iter().any(|region| {
+            region.region_type == MemoryRegionType::Usable
+                && region.range.start_addr() < kernel.end
+                && kernel.start < region.range.end_addr()
+        });
+        if overlaps {
+            log::warn!(
+                "memory map marks part of the kernel image ({:#x}..{:#x}) usable; skipping it",
+                kernel.start,
+                kernel.end
+            );
+        }
+
+        BootInfoFrameAllocator {
+            memory_map,
+            kernel,
+            next: 0,
+        }
+    }
+
+    /// Returns the physical address range of the kernel image.
+    #[cfg(test)]
+    pub fn kernel_range(&self) -> Range<u64> {
+        self.kernel.clone()
+    }
+
+    /// Iterates over the start addresses of the free `size`-byte blocks,
+    /// aligned to `size`, that lie in the usable regions at or above `next`
+    /// and outside the kernel image.
+    fn usable_blocks(&self, size: u64) -> impl Iterator<Item = u64> + '_ {
+        self.memory_map
+            .iter()
+            .filter(|region| region.region_type == MemoryRegionType::Usable)
+            .flat_map(move |region| {
+                let start = region.range.start_addr().max(self.next).next_multiple_of(size);
+                // Blocks must end inside the region
+                let end = region.range.end_addr().saturating_sub(size - 1);
+                (start..end).step_by(size as usize)
+            })
+            .filter(move |&addr| addr >= self.kernel.end || addr + size <= self.kernel.start)
+    }
+}
+
+/// Returns the smallest physical range covering every region the
+/// bootloader loaded the kernel image into, or an empty range if the map
+/// has none.
+fn kernel_range(memory_map: &MemoryMap) -> Range<u64> {
+    memory_map
+        .iter()
+        .filter(|region| region.region_type == MemoryRegionType::Kernel)
+        .map(|region| region.range.start_addr()..region.range.end_addr())
+        .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+        .unwrap_or(0..0)
+}
+
+// SAFETY: `init`'s contract guarantees the usable regions are free, and
+// `next` moves past every frame handed out, so each is returned at most once.
+unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let addr = self.usable_blocks(Size4KiB::SIZE).next()?;
+        self.next = addr + Size4KiB::SIZE;
+        Some(PhysFrame::containing_address(PhysAddr::new(addr)))
+    }
+}
+
+// SAFETY: As for 4 KiB frames; `next` also moves past every 2 MiB frame.
+unsafe impl FrameAllocator<Size2MiB> for BootInfoFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        let addr = self.usable_blocks(Size2MiB::SIZE).next()?;
+        self.next = addr + Size2MiB::SIZE;
+        Some(PhysFrame::containing_address(PhysAddr::new(addr)))
+    }
+}
+
+#[test_case]
+fn test_map_page_to_frame() {
+    use x86_64::structures::paging::{Mapper, Page, PageTableFlags, Translate};
+
+    const VALUE: u64 = 0x0123_4567_89ab_cdef;
+
+    let mut memory = MEMORY.lock();
+    let Memory {
+        mapper,
+        frame_allocator,
+    } = memory.as_mut().expect("memory not initialized");
+
+    // An address far from anything the bootloader maps
+    let page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(0xdead_beef_0000));
+    let frame = frame_allocator.allocate_frame().expect("out of frames");
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    // SAFETY: The page is unused and the frame was just allocated, so the new
+    // mapping does not alias any existing memory.
+    unsafe { mapper.map_to(page, frame, flags, frame_allocator) }
+        .expect("map_to failed")
+        .flush();
+    assert_eq!(
+        mapper.translate_addr(page.start_address()),
+        Some(frame.start_address())
+    );
+
+    // Write through the new page and read back through the physical mapping
+    let page_ptr: *mut u64 = page.start_address().as_mut_ptr();
+    let frame_ptr: *const u64 = (mapper.phys_offset() + frame.start_address().as_u64()).as_ptr();
+    // SAFETY: Both pointers refer to the frame mapped above.
+    unsafe {
+        page_ptr.write_volatile(VALUE);
+        assert_eq!(frame_ptr.read_volatile(), VALUE);
+    }
+
+    let (_, flush) = mapper.unmap(page).expect("unmap failed");
+    flush.flush();
+}
+
+#[test_case]
+fn test_a20_enabled() {
+    let memory = MEMORY.lock();
+    let offset = memory.as_ref().expect("memory not initialized").mapper.phys_offset();
+    // SAFETY: The bootloader mapped all physical memory at `offset`, and there
+    // is only one CPU.
+    assert!(unsafe { a20_enabled(offset) });
+}
+
+#[test_case]
+fn test_frames_skip_kernel_image() {
+    let mut memory = MEMORY.lock();
+    let frame_allocator = &mut memory.as_mut().expect("memory not initialized").frame_allocator;
+
+    let kernel = frame_allocator.kernel_range();
+    assert!(!kernel.is_empty());
+    for _ in 0..16 {
+        let frame: PhysFrame = frame_allocator.allocate_frame().expect("out of frames");
+        assert!(!kernel.contains(&frame.start_address().as_u64()));
+    }
+    assert!(frame_allocator
+        .usable_blocks(Size4KiB::SIZE)
+        .all(|addr| !kernel.contains(&addr)));
+}
+
+#[test_case]
+fn test_dump_mappings() {
+    use crate::allocator::{HEAP_SIZE, HEAP_START};
+    use alloc::string::String;
+
+    let memory = MEMORY.lock();
+    let mapper = &memory.as_ref().expect("memory not initialized").mapper;
+    let find = |addr: u64| {
+        let mut found = None;
+        mappings(mapper, |mapping| {
+            if (mapping.virt.as_u64()..mapping.virt.as_u64() + mapping.len).contains(&addr) {
+                found = Some(mapping);
+            }
+        });
+        found
+    };
+
+    // The VGA buffer is identity mapped
+    let vga = find(0xb8000).expect("VGA buffer not mapped");
+    assert_eq!(vga.phys.as_u64() + (0xb8000 - vga.virt.as_u64()), 0xb8000);
+    assert!(vga.flags.contains(PageTableFlags::WRITABLE));
+
+    // The heap starts a range, since nothing is mapped right below it
+    let heap = find(HEAP_START as u64).expect("heap not mapped");
+    assert_eq!(heap.virt.as_u64(), HEAP_START as u64);
+    assert_eq!(heap.page_size, 4096);
+    assert!(heap.flags.contains(PageTableFlags::WRITABLE));
+    assert!(find((HEAP_START + HEAP_SIZE - 1) as u64).is_some());
+
+    let mut dump = String::new();
+    dump_mappings(mapper, &mut dump).expect("formatting failed");
+    assert!(dump.contains(&alloc::format!("{:016x}-", HEAP_START)));
+}
+
+#[test_case]
+fn test_map_huge_region() {
+    use x86_64::structures::paging::mapper::{MappedFrame, TranslateResult};
+    use x86_64::structures::paging::Translate;
+
+    let mut memory = MEMORY.lock();
+    let memory = memory.as_mut().expect("memory not initialized");
+
+    // 4 MiB starting on a 2 MiB boundary, plus one 4 KiB page on either side
+    let aligned = VirtAddr::new(0x5555_0000_0000);
+    let start = aligned - Size4KiB::SIZE;
+    let size = (2 * Size2MiB::SIZE + 2 * Size4KiB::SIZE) as usize;
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    memory.map_huge_region(start, size, flags).expect("map_huge_region failed");
+
+    let is_huge = |addr: VirtAddr| match memory.mapper.translate(addr) {
+        TranslateResult::Mapped { frame, .. } => matches!(frame, MappedFrame::Size2MiB(_)),
+        _ => panic!("{:?} is not mapped", addr),
+    };
+    assert!(!is_huge(start));
+    assert!(is_huge(aligned));
+    assert!(is_huge(aligned + Size2MiB::SIZE + 0x1234u64));
+    assert!(!is_huge(start + (size as u64 - 1)));
+
+    // The memory is usable, up to the last byte
+    let last: *mut u8 = (start + (size as u64 - 1)).as_mut_ptr();
+    // SAFETY: The page was just mapped writable.
+    unsafe {
+        last.write_volatile(0x5a);
+        assert_eq!(last.read_volatile(), 0x5a);
+    }
+
+    // Unmap everything again
+    let Memory { mapper, .. } = memory;
+    for addr in [start, start + (size as u64 - 1)] {
+        let page = Page::<Size4KiB>::containing_address(addr);
+        mapper.unmap(page).expect("unmap failed").1.flush();
+    }
+    for addr in [aligned, aligned + Size2MiB::SIZE] {
+        let page = Page::<Size2MiB>::containing_address(addr);
+        mapper.unmap(page).expect("unmap failed").1.flush();
+    }
+}
+
+#[test_case]
+fn test_stack_trace() {
+    use alloc::string::String;
+
+    #[inline(never)]
+    fn depth(n: usize, out: &mut String) {
+        if n == 0 {
+            stack_trace(out).expect("formatting failed");
+        } else {
+            depth(n - 1, out);
+        }
+        // Keeps the recursion from becoming a loop
+        core::hint::black_box(n);
+    }
+
+    let mut trace = String::new();
+    depth(3, &mut trace);
+    let addresses = trace
+        .lines()
+        .skip(1)
+        .filter_map(|line| u64::from_str_radix(line.trim().trim_start_matches("0x"), 16).ok())
+        .filter(|&address| address != 0)
+        .count();
+    // Returns into the four `depth` frames, this test and the test runner
+    assert!(addresses >= 6, "only {} frames:\n{}", addresses, trace);
+    assert!(addresses <= MAX_FRAMES);
+}
@@ -0,0 +1,85 @@
+//! The two cascaded 8259 programmable interrupt controllers.
+//!
+//! At reset the PICs deliver IRQs 0-15 on vectors 8-15 and 0x70-0x77, which
+//! overlap the CPU exception vectors. `init` moves them to 32-47, directly
+//! after the exceptions.
+
+use pic8259::ChainedPics;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// First vector of the primary PIC, just past the 32 CPU exception vectors.
+pub const PIC_1_OFFSET: u8 = 32;
+/// First vector of the secondary PIC.
+pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+
+/// Primary PIC mask with only IRQ 0 (the timer) and IRQ 1 (the keyboard)
+/// enabled.
+const PRIMARY_MASK: u8 = !0x03;
+/// Secondary PIC mask with every line disabled.
+const SECONDARY_MASK: u8 = 0xff;
+
+// SAFETY: The offsets place all 16 IRQ vectors past the CPU exceptions.
+static PICS: Mutex<ChainedPics> =
+    Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+
+/// IDT vectors of the hardware interrupts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum InterruptIndex {
+    /// IRQ 0, the PIT timer.
+    Timer = PIC_1_OFFSET,
+    /// IRQ 1, the PS/2 keyboard.
+    Keyboard,
+}
+
+impl InterruptIndex {
+    pub fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn as_usize(self) -> usize {
+        usize::from(self.as_u8())
+    }
+}
+
+/// Remaps the PICs to `PIC_1_OFFSET`/`PIC_2_OFFSET` and unmasks the timer
+/// and keyboard.
+///
+/// Every other line stays masked, since a line without an IDT handler would
+/// fault as soon as it fired. Call `interrupts::init_idt` first. Safe to call
+/// again later; it runs with interrupts disabled.
+pub fn init() {
+    without_interrupts(|| {
+        let mut pics = PICS.lock();
+        // SAFETY: Interrupts are off while the PICs are reprogrammed, the new
+        // vectors do not collide with CPU exceptions, and only lines with an
+        // IDT handler are unmasked.
+        unsafe {
+            pics.initialize();
+            pics.write_masks(PRIMARY_MASK, SECONDARY_MASK);
+        }
+    });
+}
+
+/// Acknowledges the hardware interrupt on `vector`.
+///
+/// Every handler for a PIC vector must call this before returning, with its
+/// own vector; until then the PIC delivers no further interrupts of equal or
+/// lower priority. Vectors outside the PIC range are ignored.
+pub fn notify_end_of_interrupt(vector: u8) {
+    // SAFETY: Only vectors the PICs deliver reach the EOI, so at worst a
+    // misdirected call acknowledges an interrupt early. `init` holds the lock
+    // with interrupts disabled, so a handler can never spin on it.
+    unsafe {
+        PICS.lock().notify_end_of_interrupt(vector);
+    }
+}
+
+#[test_case]
+fn test_pic_init() {
+    init();
+    // SAFETY: Reading the mask registers has no side effects.
+    let masks = without_interrupts(|| unsafe { PICS.lock().read_masks() });
+    assert_eq!(masks, [PRIMARY_MASK, SECONDARY_MASK]);
+}
@@ -0,0 +1,34 @@
+use x86_64::instructions::port::Port;
+
+/// I/O port of QEMU's `isa-debug-exit` device, as configured in Cargo.toml
+/// (`-device isa-debug-exit,iobase=0xf4,iosize=0x04`).
+const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// Exit codes reported to the host through `isa-debug-exit`.
+///
+/// QEMU exits with `(code << 1) | 1`, so `Success` becomes host exit status
+/// 33, which bootimage maps back to a passing test run via
+/// `test-success-exit-code`. The values avoid 0 and 1 so they cannot be
+/// confused with QEMU's own exit statuses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+/// Shuts down QEMU with the given exit code.
+///
+/// Only has an effect when QEMU was started with the `isa-debug-exit` device;
+/// on real hardware (or without the device) the write is ignored and this
+/// returns normally.
+pub fn exit_qemu(exit_code: QemuExitCode) {
+    let mut port: Port<u32> = Port::new(ISA_DEBUG_EXIT_PORT);
+
+    // SAFETY: 0xf4 is reserved for the isa-debug-exit device in our QEMU
+    // configuration. Writing to it either terminates QEMU or, if nothing is
+    // listening, is a harmless write to an unused port.
+    unsafe {
+        port.write(exit_code as u32);
+    }
+}
@@ -0,0 +1,295 @@
+use core::fmt;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+/// I/O base address of the first serial port (COM1).
+pub const COM1: u16 = 0x3f8;
+
+// Register offsets from the port base
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+/// Read side of `FIFO_CONTROL`.
+#[cfg(test)]
+const INTERRUPT_ID: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const MODEM_STATUS: u16 = 6;
+
+/// Line control: divisor latch access bit, exposes the baud divisor registers.
+const LCR_DLAB: u8 = 0x80;
+/// Line control: 8 data bits, no parity, one stop bit.
+const LCR_8N1: u8 = 0x03;
+/// FIFO control: enable and clear both FIFOs; OR in the trigger level bits.
+const FCR_ENABLE_CLEAR: u8 = 0x07;
+/// Interrupt identification: both bits are set while the FIFOs are enabled.
+#[cfg(test)]
+const IIR_FIFOS_ENABLED: u8 = 0xc0;
+/// Modem control: DTR, RTS and OUT2 (OUT2 gates the IRQ line).
+const MCR_DTR_RTS_OUT2: u8 = 0x0b;
+/// Modem control: loopback, which feeds transmitted bytes to the receiver.
+const MCR_LOOPBACK: u8 = 0x10;
+/// Line status: a received byte is waiting in the data register.
+const LSR_DATA_READY: u8 = 0x01;
+/// Line status: transmit holding register empty.
+const LSR_THR_EMPTY: u8 = 0x20;
+/// Modem status: the other end asserts CTS and is ready to receive.
+const MSR_CTS: u8 = 0x10;
+
+/// Baud divisor for 38400 baud (115200 / 3).
+const BAUD_DIVISOR: u16 = 3;
+
+/// FIFO setup of the UART, chosen with `SerialPort::configure`.
+///
+/// The trigger level is how many bytes the receive FIFO collects before the
+/// UART raises a receive interrupt. A higher level means fewer interrupts at
+/// high baud rates, a lower one less latency.
+// `init` always picks `Trigger14`; the others are for callers
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FifoConfig {
+    /// FIFOs off; the UART holds a single byte in each direction.
+    Disabled,
+    Trigger1,
+    Trigger4,
+    Trigger8,
+    /// The default.
+    Trigger14,
+}
+
+impl FifoConfig {
+    /// Value written to the FIFO control register.
+    fn fcr(self) -> u8 {
+        match self {
+            FifoConfig::Disabled => 0x00,
+            FifoConfig::Trigger1 => FCR_ENABLE_CLEAR,
+            FifoConfig::Trigger4 => FCR_ENABLE_CLEAR | 0x40,
+            FifoConfig::Trigger8 => FCR_ENABLE_CLEAR | 0x80,
+            FifoConfig::Trigger14 => FCR_ENABLE_CLEAR | 0xc0,
+        }
+    }
+}
+
+/// Hardware flow control, chosen with `SerialPort::configure`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowControl {
+    /// Bytes are sent whenever the transmitter is free (the default).
+    None,
+    /// RTS is asserted to accept data, and `send` waits until the other end
+    /// asserts CTS before each byte.
+    RtsCts,
+}
+
+/// A polled 16550-compatible UART.
+///
+/// Output written here shows up on the host when QEMU is started with
+/// `-serial stdio`, which makes it the channel of choice for logs and
+/// automated tests.
+pub struct SerialPort {
+    base: u16,
+    initialized: bool,
+    fifo: FifoConfig,
+    flow_control: FlowControl,
+}
+
+impl SerialPort {
+    /// Creates a handle for the UART at `base`. No I/O happens until `init`.
+    pub const fn new(base: u16) -> SerialPort {
+        SerialPort {
+            base,
+            initialized: false,
+            fifo: FifoConfig::Trigger14,
+            flow_control: FlowControl::None,
+        }
+    }
+
+    /// Programs the UART for 38400 baud, 8N1, with the FIFO and flow control
+    /// settings of the last `configure` (by default, FIFOs with a 14-byte
+    /// trigger level and no flow control).
+    ///
+    /// Idempotent: calls after the first one do nothing.
+    pub fn init(&mut self) {
+        if self.initialized {
+            return;
+        }
+
+        // SAFETY: The offsets are the standard 16550 register layout relative
+        // to `base`, and this sequence only configures the UART itself.
+        unsafe {
+            // Polled operation: no UART interrupts
+            self.port(INTERRUPT_ENABLE).write(0x00);
+
+            // Set the baud divisor through the divisor latch
+            self.port(LINE_CONTROL).write(LCR_DLAB);
+            self.port(DATA).write((BAUD_DIVISOR & 0xff) as u8);
+            self.port(INTERRUPT_ENABLE).write((BAUD_DIVISOR >> 8) as u8);
+
+            // Clearing DLAB here also restores normal data register access
+            self.port(LINE_CONTROL).write(LCR_8N1);
+        }
+        self.configure(self.fifo, self.flow_control);
+
+        self.initialized = true;
+    }
+
+    /// Sets the FIFO trigger level and flow control.
+    ///
+    /// Takes effect immediately and also applies to a later `init`. Changing
+    /// the FIFO setup clears both FIFOs, dropping any bytes still in them.
+    /// Both settings use DTR, RTS and OUT2; with `RtsCts` the UART also holds
+    /// each byte back until the other end asserts CTS.
+    pub fn configure(&mut self, fifo: FifoConfig, flow_control: FlowControl) {
+        self.fifo = fifo;
+        self.flow_control = flow_control;
+
+        // SAFETY: Only the FIFO and modem control registers of this UART are
+        // written. The loopback bit is kept, so tests can reconfigure a port
+        // in loopback mode.
+        unsafe {
+            self.port(FIFO_CONTROL).write(fifo.fcr());
+            let loopback = self.port(MODEM_CONTROL).read() & MCR_LOOPBACK;
+            self.port(MODEM_CONTROL).write(MCR_DTR_RTS_OUT2 | loopback);
+        }
+    }
+
+    /// Returns `true` once `init` has programmed the UART.
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    /// Sends one byte, spinning until the transmit holding register is empty
+    /// and, with `FlowControl::RtsCts`, until the other end asserts CTS.
+    pub fn send(&mut self, byte: u8) {
+        // SAFETY: Reading the line and modem status registers has no side
+        // effects, and the data register is only written once the UART
+        // reports it is ready.
+        unsafe {
+            if self.flow_control == FlowControl::RtsCts {
+                while self.port(MODEM_STATUS).read() & MSR_CTS == 0 {
+                    core::hint::spin_loop();
+                }
+            }
+            while self.port(LINE_STATUS).read() & LSR_THR_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            self.port(DATA).write(byte);
+        }
+    }
+
+    /// Returns the next received byte, or `None` if none is waiting.
+    // The kernel only transmits; receiving is for callers and the tests
+    #[allow(dead_code)]
+    pub fn receive(&mut self) -> Option<u8> {
+        // SAFETY: Reading the line status register has no side effects, and
+        // the data register is only read when it holds a received byte.
+        unsafe {
+            if self.port(LINE_STATUS).read() & LSR_DATA_READY == 0 {
+                return None;
+            }
+            Some(self.port(DATA).read())
+        }
+    }
+
+    /// Enables or disables loopback mode.
+    ///
+    /// In loopback mode the UART disconnects from the line and every byte
+    /// sent is received back by `receive`, which lets tests check serial
+    /// output without a host on the other end.
+    #[allow(dead_code)]
+    pub fn set_loopback(&mut self, on: bool) {
+        // SAFETY: Only the loopback bit of the modem control register changes.
+        unsafe {
+            let mut modem_control = self.port(MODEM_CONTROL);
+            let value = modem_control.read();
+            modem_control.write(if on { value | MCR_LOOPBACK } else { value & !MCR_LOOPBACK });
+        }
+    }
+
+    fn port(&self, offset: u16) -> Port<u8> {
+        Port::new(self.base + offset)
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.init();
+        for byte in s.bytes() {
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Global handle for COM1, locked like `WRITER`.
+pub static SERIAL1: Mutex<SerialPort> = Mutex::new(SerialPort::new(COM1));
+
+/// Initializes COM1. Safe to call more than once.
+pub fn init() {
+    SERIAL1.lock().init();
+}
+
+/// Prints to the host through the serial port.
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::serial::_print(format_args!($($arg)*)));
+}
+
+/// Prints to the host through the serial port, with a newline.
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
+
+/// Backend for `serial_print!`/`serial_println!`; not meant to be called directly.
+///
+/// Like `vga_buffer::_print`, this disables interrupts while the lock is
+/// held, so an interrupt handler that prints cannot deadlock on it.
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    // With interrupts off, nothing can release a held lock
+    debug_assert!(
+        interrupts::are_enabled() || !SERIAL1.is_locked(),
+        "serial_print! with interrupts disabled while SERIAL1 is locked"
+    );
+    interrupts::without_interrupts(|| {
+        // SerialPort::write_str never fails, so the result carries no information
+        let _ = SERIAL1.lock().write_fmt(args);
+    });
+}
+
+#[test_case]
+fn test_configure_fifo_and_flow_control() {
+    fn fifos_enabled(serial: &SerialPort) -> u8 {
+        // SAFETY: Reading the interrupt ID register of a polled UART only
+        // reports its state.
+        unsafe { serial.port(INTERRUPT_ID).read() & IIR_FIFOS_ENABLED }
+    }
+
+    let mut serial = SERIAL1.lock();
+    serial.init();
+
+    // The trigger level itself cannot be read back, but the interrupt ID
+    // register reports whether the FIFOs are on
+    serial.configure(FifoConfig::Trigger4, FlowControl::None);
+    assert_eq!(fifos_enabled(&serial), IIR_FIFOS_ENABLED);
+    serial.configure(FifoConfig::Disabled, FlowControl::None);
+    assert_eq!(fifos_enabled(&serial), 0);
+    // SAFETY: Reading the modem control register has no side effects.
+    assert_eq!(unsafe { serial.port(MODEM_CONTROL).read() }, MCR_DTR_RTS_OUT2);
+
+    // In loopback mode CTS follows the port's own RTS, so a byte sent with
+    // flow control still goes through
+    serial.set_loopback(true);
+    serial.configure(FifoConfig::Trigger1, FlowControl::RtsCts);
+    while serial.receive().is_some() {}
+    serial.send(b'Z');
+    let received = (0..10_000).find_map(|_| serial.receive());
+    serial.set_loopback(false);
+    serial.configure(FifoConfig::Trigger14, FlowControl::None);
+    assert_eq!(received, Some(b'Z'));
+}
@@ -0,0 +1,36 @@
+//! Crude timing based on the CPU's time-stamp counter.
+//!
+//! The TSC counts CPU cycles (or, on newer CPUs, ticks of a constant-rate
+//! reference clock) since reset. Its frequency is never measured here, so
+//! cycle counts do not translate to wall-clock time: the same delay can be
+//! noticeably longer or shorter depending on the CPU and on QEMU's TCG/KVM
+//! mode. That is good enough for benchmarks and for pacing animations before
+//! a proper timer is set up.
+
+use core::arch::x86_64::_rdtsc;
+
+/// Returns the current value of the time-stamp counter.
+pub fn read_tsc() -> u64 {
+    // SAFETY: RDTSC is available on every x86_64 CPU and has no side effects.
+    unsafe { _rdtsc() }
+}
+
+/// Spins until the time-stamp counter has advanced by at least `cycles`.
+///
+/// Keeps the CPU busy the whole time; see the module docs for why the
+/// resulting delay is only approximate.
+pub fn busy_wait_cycles(cycles: u64) {
+    let start = read_tsc();
+    while read_tsc().wrapping_sub(start) < cycles {
+        core::hint::spin_loop();
+    }
+}
+
+#[test_case]
+fn test_busy_wait_cycles_advances_tsc() {
+    const CYCLES: u64 = 100_000;
+
+    let start = read_tsc();
+    busy_wait_cycles(CYCLES);
+    assert!(read_tsc() - start >= CYCLES);
+}
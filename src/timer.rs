@@ -0,0 +1,160 @@
+//! Periodic timer interrupt from channel 0 of the 8253/8254 PIT.
+//!
+//! The PIT divides a fixed 1.193182 MHz input clock by a 16-bit divisor and
+//! raises IRQ 0 each time the count runs out. `interrupts` routes that IRQ to
+//! `tick`, which counts it; `ticks`, `sleep_ticks` and `uptime_ms` build on
+//! the count.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::port::Port;
+
+/// Frequency `kernel_main` programs the timer to.
+pub const TIMER_HZ: u32 = 100;
+/// Lowest frequency whose divisor still fits in 16 bits.
+pub const MIN_HZ: u32 = 19;
+
+/// Input clock of the PIT in Hz.
+const PIT_FREQUENCY: u32 = 1_193_182;
+/// Channel 0 data port; takes the divisor low byte first.
+const CHANNEL_0_PORT: u16 = 0x40;
+/// Mode/command register.
+const COMMAND_PORT: u16 = 0x43;
+/// Channel 0, access low byte then high byte, mode 3 (square wave), binary.
+const CHANNEL_0_SQUARE_WAVE: u8 = 0x36;
+
+/// Timer interrupts received since boot.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+/// Divisor channel 0 is currently programmed with; 0 before `init`.
+static DIVISOR: AtomicU64 = AtomicU64::new(0);
+/// Tick count and uptime at the last frequency change. Ticks after that
+/// point are converted at the current rate.
+static BASE_TICKS: AtomicU64 = AtomicU64::new(0);
+static BASE_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Programs PIT channel 0 to interrupt `hz` times per second.
+///
+/// Same as `set_frequency`. The interrupt only arrives once the PIC is set
+/// up and interrupts are enabled.
+pub fn init(hz: u32) {
+    set_frequency(hz);
+}
+
+/// Reprograms PIT channel 0 to interrupt `hz` times per second.
+///
+/// The divisor (1193182 / `hz`) must fit in 16 bits, so frequencies are
+/// clamped to `MIN_HZ`..=1193182. Higher frequencies give `sleep_ticks` and
+/// `uptime_ms` finer resolution at the cost of more interrupts. Uptime
+/// already counted is kept, and later ticks are converted at the new rate.
+pub fn set_frequency(hz: u32) {
+    let divisor = (PIT_FREQUENCY / hz.clamp(MIN_HZ, PIT_FREQUENCY)) as u16;
+    let mut command: Port<u8> = Port::new(COMMAND_PORT);
+    let mut data: Port<u8> = Port::new(CHANNEL_0_PORT);
+
+    // No tick may land between taking the base and switching the rate
+    without_interrupts(|| {
+        BASE_MS.store(uptime_ms(), Ordering::Relaxed);
+        BASE_TICKS.store(ticks(), Ordering::Relaxed);
+        DIVISOR.store(u64::from(divisor), Ordering::Relaxed);
+
+        // SAFETY: 0x43 and 0x40 are the PIT command and channel 0 data ports.
+        // Reprogramming channel 0 only changes the timer interrupt rate.
+        unsafe {
+            command.write(CHANNEL_0_SQUARE_WAVE);
+            data.write(divisor as u8);
+            data.write((divisor >> 8) as u8);
+        }
+    });
+}
+
+/// Returns the divisor channel 0 is programmed with, or 0 before `init`.
+// Timing API for callers; kernel_main only idles
+#[allow(dead_code)]
+pub fn divisor() -> u16 {
+    DIVISOR.load(Ordering::Relaxed) as u16
+}
+
+/// Counts one timer interrupt. Called by the timer interrupt handler.
+pub fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the number of timer interrupts since boot.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Returns the milliseconds elapsed since the timer was started, based on
+/// the tick count.
+///
+/// The resolution is one tick, 10 ms at the default `TIMER_HZ`.
+pub fn uptime_ms() -> u64 {
+    let divisor = DIVISOR.load(Ordering::Relaxed);
+    let since_base = ticks() - BASE_TICKS.load(Ordering::Relaxed);
+    BASE_MS.load(Ordering::Relaxed) + since_base * divisor * 1000 / u64::from(PIT_FREQUENCY)
+}
+
+/// Halts until at least `n` more timer interrupts have arrived.
+///
+/// Interrupts must be enabled; otherwise this never returns.
+#[allow(dead_code)]
+pub fn sleep_ticks(n: u64) {
+    let target = ticks() + n;
+    while ticks() < target {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Spins until `uptime_ms` has advanced by at least `ms`.
+///
+/// Unlike `sleep_ticks` the CPU never halts, but the wait still relies on
+/// timer interrupts, which must be enabled.
+#[allow(dead_code)]
+pub fn busy_sleep_ms(ms: u64) {
+    let target = uptime_ms() + ms;
+    while uptime_ms() < target {
+        core::hint::spin_loop();
+    }
+}
+
+#[test_case]
+fn test_sleep_ticks() {
+    let start = ticks();
+    sleep_ticks(5);
+    assert!(ticks() >= start + 5);
+}
+
+#[test_case]
+fn test_set_frequency() {
+    set_frequency(1000);
+    assert_eq!(divisor(), 1193);
+    // The hardware counter runs down from the divisor, so it never exceeds it
+    for _ in 0..10 {
+        let mut command: Port<u8> = Port::new(COMMAND_PORT);
+        let mut data: Port<u8> = Port::new(CHANNEL_0_PORT);
+        // SAFETY: Latching channel 0 and reading the latched count back does
+        // not disturb the running counter.
+        let count = unsafe {
+            command.write(0x00);
+            u16::from(data.read()) | u16::from(data.read()) << 8
+        };
+        assert!(count <= 1193);
+    }
+
+    // At 1000 Hz every tick is (almost exactly) one millisecond
+    let (start_ticks, start_ms) = without_interrupts(|| (ticks(), uptime_ms()));
+    sleep_ticks(20);
+    let (end_ticks, end_ms) = without_interrupts(|| (ticks(), uptime_ms()));
+    assert!((end_ms - start_ms).abs_diff(end_ticks - start_ticks) <= 1);
+
+    let start_ms = uptime_ms();
+    busy_sleep_ms(5);
+    assert!(uptime_ms() >= start_ms + 5);
+
+    // Too low for a 16-bit divisor, so clamped to MIN_HZ
+    set_frequency(1);
+    assert_eq!(divisor(), 62799);
+
+    set_frequency(TIMER_HZ);
+    assert_eq!(divisor(), 11931);
+}
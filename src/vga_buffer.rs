@@ -1,8 +1,17 @@
-use volatile::Volatile;
 use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::serial::{SerialPort, SERIAL1};
+use crate::vga_mode::{self, TextMode};
+use crate::{cp437, font, time};
 
 pub const BUFFER_HEIGHT: usize = 25;
 pub const BUFFER_WIDTH: usize = 80;
+/// Rows in the tallest supported text mode (80x50); see `vga_mode::TextMode`.
+pub const MAX_BUFFER_HEIGHT: usize = 50;
+/// Physical (and, under the bootloader's identity mapping, virtual) address
+/// of the VGA text buffer.
+const VGA_BUFFER_ADDR: usize = 0xb8000;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -25,42 +34,315 @@ pub enum Color {
     White = 15,
 }
 
+// Color API for callers; kernel_main only builds color codes
+#[allow(dead_code)]
+impl Color {
+    /// All colors, indexed by their 4-bit VGA value.
+    const ALL: [Color; 16] = [
+        Color::Black,
+        Color::Blue,
+        Color::Green,
+        Color::Cyan,
+        Color::Red,
+        Color::Magenta,
+        Color::Brown,
+        Color::LightGray,
+        Color::DarkGray,
+        Color::LightBlue,
+        Color::LightGreen,
+        Color::LightCyan,
+        Color::LightRed,
+        Color::Pink,
+        Color::Yellow,
+        Color::White,
+    ];
+
+    /// Returns the color with VGA value `n`, or `None` if `n` is 16 or more.
+    pub fn from_u8(n: u8) -> Option<Color> {
+        Color::ALL.get(n as usize).copied()
+    }
+
+    /// Returns the 4-bit VGA value of this color.
+    pub fn as_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+impl TryFrom<u8> for Color {
+    /// The rejected value.
+    type Error = u8;
+
+    fn try_from(n: u8) -> Result<Color, u8> {
+        Color::from_u8(n).ok_or(n)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct ColorCode(u8);
 
+/// Attribute bit 7: blink, or bright background if blinking is disabled in the VGA mode.
+const BLINK_BIT: u8 = 0x80;
+
+#[allow(dead_code)]
 impl ColorCode {
-    fn new(foreground: Color, background: Color) -> ColorCode {
+    /// Create a ColorCode from foreground and background colors.
+    pub const fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
     
     /// Create a ColorCode from foreground and background colors.
-    /// Public for use in panic handler.
+    /// Alias of `new`, kept for the panic handler.
     pub fn from_colors(foreground: Color, background: Color) -> ColorCode {
-        ColorCode((background as u8) << 4 | (foreground as u8))
+        ColorCode::new(foreground, background)
+    }
+
+    /// Create a ColorCode from a raw VGA attribute byte.
+    ///
+    /// Every byte is a valid attribute: bits 0-3 are the foreground, bits 4-6
+    /// the background and bit 7 blink (or bright background).
+    pub fn from_u8(raw: u8) -> ColorCode {
+        ColorCode(raw)
+    }
+
+    /// Returns the raw VGA attribute byte.
+    pub fn to_u8(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the foreground color (bits 0-3).
+    pub fn foreground(self) -> Color {
+        Color::ALL[(self.0 & 0x0f) as usize]
+    }
+
+    /// Returns the background color (bits 4-6).
+    ///
+    /// Bit 7 is the blink flag, so a bright background set with `new` reads
+    /// back as its dark counterpart.
+    pub fn background(self) -> Color {
+        Color::ALL[((self.0 >> 4) & 0x07) as usize]
     }
+
+    /// Returns this color code with the blink bit (bit 7) set or cleared.
+    ///
+    /// Whether bit 7 means "blink" or "bright background" depends on the
+    /// attribute controller's mode control register; the BIOS default is blink.
+    /// In blink mode only the 8 dark colors are available as backgrounds, since
+    /// bit 7 no longer selects the bright half of the palette.
+    pub fn with_blink(self, blink: bool) -> ColorCode {
+        if blink {
+            ColorCode(self.0 | BLINK_BIT)
+        } else {
+            ColorCode(self.0 & !BLINK_BIT)
+        }
+    }
+
+    /// Returns this color code with foreground and background swapped.
+    ///
+    /// The background nibble only has 3 color bits (bit 7 is blink), so a
+    /// bright foreground becomes its dark counterpart when moved to the
+    /// background.
+    pub fn inverted(self) -> ColorCode {
+        let foreground = self.0 & 0x0f;
+        let background = (self.0 >> 4) & 0x07;
+        ColorCode((foreground & 0x07) << 4 | background)
+    }
+}
+
+/// What a newline on the last row of the scroll region does.
+// Selected through `WriterBuilder`, which the kernel does not use yet
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowBehavior {
+    /// Scroll the region up by one row (the default).
+    Scroll,
+    /// Continue on the top row of the region, clearing it first.
+    Wrap,
+    /// Stay on the last row, clearing it first; nothing ever moves, as on a
+    /// fixed dashboard.
+    Clamp,
 }
 
+/// A single VGA text cell: a Code Page 437 character and its attribute byte.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
-struct ScreenChar {
-    ascii_character: u8,
-    color_code: ColorCode,
+pub struct ScreenChar {
+    pub ascii_character: u8,
+    pub color_code: ColorCode,
+}
+
+/// Cell storage laid out like the VGA text buffer, sized for the largest
+/// text mode.
+///
+/// Writers normally draw to the real buffer at 0xb8000. `Buffer::blank`
+/// creates an ordinary in-memory one for `Writer::from_buffer`, so output can
+/// be checked without touching the screen.
+///
+/// Every access is volatile, so the compiler never elides or merges writes to
+/// the memory-mapped buffer. Rows are 8-byte aligned (0xb8000 is, and each row
+/// is 160 bytes), so whole rows can be written four cells at a time.
+#[repr(C, align(8))]
+pub struct Buffer {
+    chars: [[ScreenChar; BUFFER_WIDTH]; MAX_BUFFER_HEIGHT],
+}
+
+#[allow(dead_code)]
+impl Buffer {
+    /// Creates a buffer of blank, light gray on black cells.
+    pub fn blank() -> Buffer {
+        let cell = ScreenChar {
+            ascii_character: b' ',
+            color_code: ColorCode::new(Color::LightGray, Color::Black),
+        };
+        Buffer {
+            chars: [[cell; BUFFER_WIDTH]; MAX_BUFFER_HEIGHT],
+        }
+    }
+
+    /// Reads the cell at `(row, col)`. Panics if the position is out of range.
+    fn read(&self, row: usize, col: usize) -> ScreenChar {
+        // SAFETY: The reference points to a valid, aligned cell.
+        unsafe { core::ptr::read_volatile(&self.chars[row][col]) }
+    }
+
+    /// Writes the cell at `(row, col)`. Panics if the position is out of range.
+    fn write(&mut self, row: usize, col: usize, cell: ScreenChar) {
+        // SAFETY: The reference points to a valid, aligned cell.
+        unsafe { core::ptr::write_volatile(&mut self.chars[row][col], cell) }
+    }
+
+    /// Writes a whole row with one volatile 64-bit store per four cells, a
+    /// quarter of the stores `write` would take. Panics if `row` is out of
+    /// range.
+    fn write_row(&mut self, row: usize, cells: &[ScreenChar; BUFFER_WIDTH]) {
+        let words = self.chars[row].as_mut_ptr().cast::<u64>();
+        for (i, quad) in cells.chunks_exact(4).enumerate() {
+            // Little-endian, so each cell's character byte comes first
+            let word = quad.iter().rev().fold(0, |word, cell| {
+                word << 16 | u64::from(cell.color_code.0) << 8 | u64::from(cell.ascii_character)
+            });
+            // SAFETY: The row is 8-byte aligned and BUFFER_WIDTH / 4 words
+            // long, so every store is aligned and stays inside it.
+            unsafe { core::ptr::write_volatile(words.add(i), word) }
+        }
+    }
+}
+
+/// Ring of rows that scrolled off the top of a writer's scroll region.
+///
+/// At about 32 KB this is too large to live inside `Writer`, which is moved
+/// around by value while it is built. The global `WRITER` uses a static
+/// instance; other writers have no scrollback unless given one with
+/// `Writer::set_history`.
+pub struct History {
+    rows: [[ScreenChar; BUFFER_WIDTH]; SCROLLBACK_ROWS],
+    /// Index in `rows` the next evicted row is stored at.
+    head: usize,
+    /// Number of valid rows in `rows`.
+    len: usize,
+}
+
+#[allow(dead_code)]
+impl History {
+    /// Creates an empty history. `const`, so it can initialize a `static`.
+    pub const fn new() -> History {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: ColorCode::new(Color::LightGray, Color::Black),
+        };
+        History {
+            rows: [[blank; BUFFER_WIDTH]; SCROLLBACK_ROWS],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Number of rows stored, at most `SCROLLBACK_ROWS`.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the row `index` rows after the oldest one.
+    fn row(&self, index: usize) -> &[ScreenChar; BUFFER_WIDTH] {
+        &self.rows[(self.head + SCROLLBACK_ROWS - self.len + index) % SCROLLBACK_ROWS]
+    }
+
+    /// Appends `row`, dropping the oldest row once the history is full.
+    fn push(&mut self, row: [ScreenChar; BUFFER_WIDTH]) {
+        self.rows[self.head] = row;
+        self.head = (self.head + 1) % SCROLLBACK_ROWS;
+        if self.len < SCROLLBACK_ROWS {
+            self.len += 1;
+        }
+    }
 }
 
-struct Buffer {
-    chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-pub struct Writer {
+pub struct Writer<'a> {
+    row_position: usize,
     column_position: usize,
     color_code: ColorCode,
-    buffer: &'static mut Buffer,
+    /// Color used when erasing regions, independent of the active drawing color.
+    default_color_code: ColorCode,
+    /// When set, row 0 holds a title bar that scrolling never touches.
+    title_active: bool,
+    /// When set, the last row holds a status line that scrolling never touches.
+    status_active: bool,
+    /// When set, starting a new line first fills it with blanks in the current color.
+    line_fill: bool,
+    /// When set, `write_string` moves words that do not fit to the next line.
+    word_wrap: bool,
+    /// When set, text after a `\r` replaces the whole line; see
+    /// `set_transient_cr`.
+    transient_cr: bool,
+    /// Set by a `\r` in transient mode until the next character decides
+    /// whether the line gets cleared.
+    cr_pending: bool,
+    /// Tab stops are placed every `tab_width` columns.
+    tab_width: usize,
+    /// Shown for characters CP437 has no glyph for.
+    fallback_glyph: u8,
+    /// First and last row (inclusive) of the region that scrolls on newline.
+    scroll_top: usize,
+    scroll_bottom: usize,
+    /// Number of rows in the current text mode (`BUFFER_HEIGHT` or
+    /// `MAX_BUFFER_HEIGHT`). Every text mode is `BUFFER_WIDTH` columns wide.
+    height: usize,
+    /// Off-screen copy of the screen; all drawing happens here first.
+    shadow: [[ScreenChar; BUFFER_WIDTH]; MAX_BUFFER_HEIGHT],
+    /// Bit `n` is set when row `n` of `shadow` differs from the VGA buffer.
+    dirty: u64,
+    /// When set, every public drawing operation ends with a `flush`.
+    auto_flush: bool,
+    /// When set, every newline ends with a `flush`, even with auto-flush off.
+    flush_on_newline: bool,
+    /// What a newline on the last row of the scroll region does.
+    overflow: OverflowBehavior,
+    /// Rows that scrolled off the top of the scroll region, if this writer
+    /// keeps scrollback.
+    history: Option<&'a mut History>,
+    /// How many rows the view is scrolled back; 0 shows live output.
+    view_offset: usize,
+    buffer: &'a mut Buffer,
 }
 
-impl Writer {
+// Drawing API for callers; kernel_main only uses a few methods so far
+#[allow(dead_code)]
+impl<'a> Writer<'a> {
     /// Creates a new Writer that writes to the VGA text buffer.
     ///
+    /// Uses the default configuration (yellow on black, 8-column tabs,
+    /// scrolling enabled); see `WriterBuilder` to customize it.
+    ///
     /// # Safety
     ///
     /// This function is unsafe because it creates a raw pointer to the VGA text buffer
@@ -69,48 +351,405 @@ impl Writer {
     /// 1. The VGA text buffer at 0xb8000 is a memory-mapped I/O region that is always
     ///    available in x86_64 systems, even in early boot stages.
     /// 2. The bootloader ensures we're in a valid memory context before calling kernel_main.
-    /// 3. We use `Volatile<T>` wrapper to prevent compiler optimizations that could
+    /// 3. `Buffer` only uses volatile reads and writes, so the compiler cannot
     ///    eliminate writes to this memory-mapped region.
     /// 4. The buffer is only accessed through safe methods that use volatile operations.
     ///
     /// The static WRITER is initialized at compile time, but the actual memory access
     /// only occurs when `lock()` is called, which happens after kernel_main starts.
-    pub fn new() -> Writer {
-        Writer {
-            column_position: 0,
-            color_code: ColorCode::new(Color::Yellow, Color::Black),
-            buffer: unsafe {
-                // SAFETY: 0xb8000 is the standard VGA text buffer address in x86_64.
-                // This address is guaranteed to be valid and writable in the bootloader
-                // environment. We cast to *mut Buffer and immediately create a reference,
-                // which is safe because Buffer is a simple struct with no invariants
-                // that need to be maintained, and we only access it through Volatile<T>.
-                &mut *(0xb8000 as *mut Buffer)
-            },
+    pub fn new() -> Writer<'static> {
+        WriterBuilder::new().build()
+    }
+
+    /// Creates a writer with the default configuration that draws into
+    /// `buffer` instead of the VGA text buffer.
+    ///
+    /// Meant for tests: output can be inspected with `read_cell` while the
+    /// screen stays untouched, and the hardware cursor is left alone. Mode
+    /// changes such as `set_text_mode` still program the VGA card. The buffer
+    /// is only borrowed, so a `Buffer::blank()` on the stack will do.
+    pub fn from_buffer(buffer: &'a mut Buffer) -> Writer<'a> {
+        WriterBuilder::new().build_with_buffer(buffer)
+    }
+
+    /// Copies every row changed since the last flush to the VGA buffer.
+    ///
+    /// Drawing operations only update the writer's off-screen shadow buffer.
+    /// This writes the dirty rows to 0xb8000 with volatile 64-bit stores, four
+    /// cells each, and skips rows that have not changed. The hardware buffer is
+    /// never read.
+    ///
+    /// While scrollback history is being viewed, rows of the scroll region stay
+    /// dirty and are held back until `scroll_to_bottom`.
+    pub fn flush(&mut self) {
+        for row in 0..self.height {
+            if self.dirty & (1 << row) == 0 {
+                continue;
+            }
+            if self.view_offset > 0 && row >= self.first_text_row() && row <= self.last_text_row() {
+                continue;
+            }
+            self.buffer.write_row(row, &self.shadow[row]);
+            self.dirty &= !(1 << row);
+        }
+    }
+
+    /// Scrolls the view `lines` rows back into the scrollback history.
+    ///
+    /// Only the scroll region is repainted; rows outside it stay live. While
+    /// history is shown, new output still goes to the writer but does not
+    /// appear until `scroll_to_bottom`, and the view stays on the same rows as
+    /// more output scrolls by. Scrolling past the oldest row stops there.
+    pub fn scroll_up(&mut self, lines: usize) {
+        let offset = (self.view_offset + lines).min(self.history_len());
+        if offset != self.view_offset {
+            self.view_offset = offset;
+            self.draw_history_view();
+        }
+    }
+
+    /// Scrolls the view `lines` rows forward, returning to live output when
+    /// it reaches the bottom.
+    pub fn scroll_down(&mut self, lines: usize) {
+        if self.view_offset == 0 {
+            return;
+        }
+        self.view_offset = self.view_offset.saturating_sub(lines);
+        if self.view_offset == 0 {
+            self.scroll_to_bottom();
+        } else {
+            self.draw_history_view();
+        }
+    }
+
+    /// Leaves history view and shows the live output, including anything
+    /// written in the meantime.
+    pub fn scroll_to_bottom(&mut self) {
+        self.view_offset = 0;
+        for row in self.first_text_row()..=self.last_text_row() {
+            self.dirty |= 1 << row;
+        }
+        self.flush();
+    }
+
+    /// Returns `true` while the view is scrolled back into history.
+    pub fn is_viewing_history(&self) -> bool {
+        self.view_offset > 0
+    }
+
+    /// Gives the writer a scrollback history, replacing any it had.
+    ///
+    /// `None` turns scrollback off. A history view in progress returns to
+    /// live output first. Rows already in `history` stay available, so a
+    /// history taken from one writer with `take_history` carries over.
+    pub fn set_history(&mut self, history: Option<&'a mut History>) {
+        if self.is_viewing_history() {
+            self.scroll_to_bottom();
+        }
+        self.history = history;
+    }
+
+    /// Removes and returns the writer's scrollback history, leaving it
+    /// without one. See `set_history`.
+    pub fn take_history(&mut self) -> Option<&'a mut History> {
+        if self.is_viewing_history() {
+            self.scroll_to_bottom();
+        }
+        self.history.take()
+    }
+
+    /// Draws an overview of the whole scrollback as a one-column strip at
+    /// `col`, down the scroll region, like an editor's minimap.
+    ///
+    /// The strip covers the history followed by the live rows. Each row of it
+    /// summarizes an equal share of those lines, shaded by how many of their
+    /// cells are non-blank: blank when none are, then `░` for up to a third,
+    /// `▒` for up to two thirds and `▓` beyond that. Rows summarizing lines
+    /// that are currently on screen get a blue background. Columns past the
+    /// right edge are clamped.
+    ///
+    /// The strip is an overlay: it goes straight to the screen without
+    /// touching the text, and disappears when the rows under it are next
+    /// redrawn, so call this again after new output or scrolling.
+    pub fn draw_minimap(&mut self, col: usize) {
+        const SHADES: [(u8, Color); 4] = [
+            (b' ', Color::LightGray),
+            (0xb0, Color::DarkGray),
+            (0xb1, Color::LightGray),
+            (0xb2, Color::White),
+        ];
+
+        let col = col.min(BUFFER_WIDTH - 1);
+        let top = self.first_text_row();
+        let rows = self.last_text_row() - top + 1;
+        let lines = self.history_len() + rows;
+        let first_visible = self.history_len() - self.view_offset;
+        let visible = first_visible..first_visible + rows;
+
+        for row in 0..rows {
+            let start = row * lines / rows;
+            let end = ((row + 1) * lines / rows).max(start + 1);
+            let filled: usize = (start..end)
+                .map(|index| {
+                    let line = self.scrollback_line(index);
+                    line.iter().filter(|cell| cell.ascii_character != b' ').count()
+                })
+                .sum();
+            // Thirds of the summarized cells, rounded up
+            let total = (end - start) * BUFFER_WIDTH;
+            let level = (filled * 3).div_ceil(total).min(3);
+
+            let (glyph, foreground) = SHADES[level];
+            let on_screen = start < visible.end && end > visible.start;
+            let background = if on_screen { Color::Blue } else { Color::Black };
+            self.buffer.write(top + row, col, ScreenChar {
+                ascii_character: glyph,
+                color_code: ColorCode::new(foreground, background),
+            });
+            // The next flush of this row paints the text back over the strip
+            self.dirty |= 1 << (top + row);
+        }
+    }
+
+    /// Number of rows in the scrollback history; 0 without one.
+    fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, |history| history.len())
+    }
+
+    /// Returns line `index` of the scrollback, counting the history from its
+    /// oldest row and then the live rows of the scroll region.
+    fn scrollback_line(&self, index: usize) -> &[ScreenChar; BUFFER_WIDTH] {
+        let history_len = self.history_len();
+        match self.history.as_deref() {
+            Some(history) if index < history_len => history.row(index),
+            _ => &self.shadow[self.first_text_row() + index - history_len],
+        }
+    }
+
+    /// Paints the scroll region with the rows `view_offset` rows above the
+    /// live output, taken from history and then from the live rows.
+    fn draw_history_view(&mut self) {
+        let top = self.first_text_row();
+        let start = self.history_len() - self.view_offset;
+        for row in top..=self.last_text_row() {
+            let cells = *self.scrollback_line(start + (row - top));
+            self.buffer.write_row(row, &cells);
+        }
+    }
+
+    /// Stores `row` of the shadow buffer in the scrollback history, if there
+    /// is one.
+    fn push_history(&mut self, row: usize) {
+        let Some(history) = self.history.as_deref_mut() else {
+            return;
+        };
+        history.push(self.shadow[row]);
+        // Keep a history view on the rows it was showing
+        if self.view_offset > 0 {
+            self.view_offset = (self.view_offset + 1).min(history.len());
+        }
+    }
+
+    /// Enables or disables automatic flushing (enabled by default).
+    ///
+    /// With auto-flush on, every public drawing operation is visible as soon as
+    /// it returns. Turn it off to batch a burst of output (e.g. a full redraw or
+    /// a long log dump) and call `flush` once at the end, so the screen never
+    /// shows a half-scrolled frame. Turning it back on flushes immediately.
+    pub fn set_auto_flush(&mut self, on: bool) {
+        self.auto_flush = on;
+        if on {
+            self.flush();
         }
     }
 
+    /// Enables or disables flushing at every newline (disabled by default).
+    ///
+    /// Meant for use with auto-flush off: output within a line is batched, but
+    /// each completed line, including any scroll it causes, reaches the screen
+    /// right away. Wrapping at the right edge counts as a newline.
+    pub fn set_flush_on_newline(&mut self, on: bool) {
+        self.flush_on_newline = on;
+    }
+
+    /// Chooses what a newline on the last row of the scroll region does;
+    /// see `OverflowBehavior`.
+    pub fn set_overflow_behavior(&mut self, behavior: OverflowBehavior) {
+        self.overflow = behavior;
+    }
+
+    pub fn overflow_behavior(&self) -> OverflowBehavior {
+        self.overflow
+    }
+
     pub fn set_color(&mut self, foreground: Color, background: Color) {
         self.color_code = ColorCode::new(foreground, background);
     }
 
+    /// Returns the color used for subsequent output.
+    pub fn color_code(&self) -> ColorCode {
+        self.color_code
+    }
+
+    /// Changes the foreground color, keeping the current background and blink.
+    pub fn set_foreground(&mut self, foreground: Color) {
+        self.color_code = ColorCode(self.color_code.0 & 0xf0 | foreground as u8);
+    }
+
+    /// Changes the background color, keeping the current foreground.
+    ///
+    /// Like `set_color`, a bright background sets bit 7 (see `with_blink`).
+    pub fn set_background(&mut self, background: Color) {
+        self.color_code = ColorCode(self.color_code.0 & 0x0f | (background as u8) << 4);
+    }
+
+    /// Turns the blink attribute on or off for subsequent output.
+    ///
+    /// Colors stay as set; see `ColorCode::with_blink` for the hardware caveats.
+    pub fn set_blink(&mut self, on: bool) {
+        self.color_code = self.color_code.with_blink(on);
+    }
+
+    /// Sets the CP437 byte shown for characters CP437 has no glyph for, such
+    /// as emoji or CJK text. Defaults to `■` (0xfe); `b'?'` is a common
+    /// alternative. Control characters always show as `■`.
+    pub fn set_fallback_glyph(&mut self, glyph: u8) {
+        self.fallback_glyph = glyph;
+    }
+
+    /// Sets the distance between tab stops, clamped to `1..=16` columns.
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_width = width.clamp(1, MAX_TAB_WIDTH);
+    }
+
+    /// Enables or disables line fill.
+    ///
+    /// Normally only cells that receive characters take the current color, so a
+    /// short line written after a color change keeps the old background in its
+    /// trailing cells. With line fill enabled, moving to a new line (by a
+    /// newline or a wrap) blanks the whole row in the current color, giving the
+    /// line a uniform background. A `\r` does not, so overwriting part of a line
+    /// keeps the rest of it.
+    pub fn set_line_fill(&mut self, on: bool) {
+        self.line_fill = on;
+    }
+
+    /// Enables or disables word wrap for `write_string` (and `print!`).
+    ///
+    /// Off by default, so text wraps at whatever character hits the right
+    /// edge. When on, a word that would cross the edge starts on a new line
+    /// instead, and a space that falls exactly on the edge is dropped. Words
+    /// longer than a full row also start a new line and then break mid-word.
+    /// `wrapped_row_count` predicts the resulting layout.
+    pub fn set_word_wrap(&mut self, on: bool) {
+        self.word_wrap = on;
+    }
+
+    /// Enables or disables transient carriage returns (off by default).
+    ///
+    /// Normally `\r` only moves the cursor back to the start of the line, so
+    /// shorter text written after it leaves the end of the old text in place.
+    /// With transient carriage returns, a `\r` not followed by `\n` marks the
+    /// line as transient: the next character clears it first, so progress
+    /// output like `"10%\r20%\r100%\n"` shows only its latest update.
+    pub fn set_transient_cr(&mut self, on: bool) {
+        self.transient_cr = on;
+        self.cr_pending = false;
+    }
+
+    /// Moves the cursor to `(row, col)`; subsequent output starts there.
+    ///
+    /// Rows are clamped to the scroll region and columns to the last column.
+    /// Row 0 is skipped while a title bar is active, and the last row while a
+    /// status line is. The VGA hardware cursor is moved to match.
+    pub fn set_cursor_position(&mut self, row: usize, col: usize) {
+        self.row_position = row.clamp(self.first_text_row(), self.last_text_row());
+        self.column_position = col.min(BUFFER_WIDTH - 1);
+        self.update_hardware_cursor();
+    }
+
+    /// Returns the cursor position as `(row, col)`.
+    ///
+    /// `col` can equal `BUFFER_WIDTH` when the current row is full; the next
+    /// character then wraps to a new line.
+    pub fn get_cursor_position(&self) -> (usize, usize) {
+        (self.row_position, self.column_position)
+    }
+
+    /// Reads the character byte and color shown at `(row, col)`.
+    ///
+    /// This is a volatile read of the VGA buffer, so it reports what is on
+    /// screen: output not yet flushed is not included, and a scrollback view
+    /// shows history. Returns `None` for positions outside the screen.
+    pub fn read_cell(&self, row: usize, col: usize) -> Option<(u8, ColorCode)> {
+        if row >= self.height || col >= BUFFER_WIDTH {
+            return None;
+        }
+        let cell = self.buffer.read(row, col);
+        Some((cell.ascii_character, cell.color_code))
+    }
+
     pub fn write_byte(&mut self, byte: u8) {
+        self.put_byte(byte);
+        self.present();
+        self.update_hardware_cursor();
+    }
+
+    /// Writes one byte at the cursor without syncing the hardware cursor.
+    fn put_byte(&mut self, byte: u8) {
+        // A transient line is replaced by whatever follows the `\r`, unless
+        // that is a newline, which keeps it
+        if self.cr_pending && byte != b'\r' {
+            self.cr_pending = false;
+            if byte != b'\n' {
+                self.clear_row(self.row_position);
+            }
+        }
+
         match byte {
             b'\n' => self.new_line(),
+            // Carriage return: back to the start of the line, which is then
+            // overwritten in place; nothing is cleared unless the line is
+            // transient
+            b'\r' => {
+                self.column_position = 0;
+                self.cr_pending = self.transient_cr;
+            }
+            b'\t' => {
+                // Advance to the next tab stop, filling skipped cells with
+                // spaces; a tab stop past the right edge starts a new line
+                let next_stop = (self.column_position / self.tab_width + 1) * self.tab_width;
+                if next_stop > BUFFER_WIDTH {
+                    self.new_line();
+                } else {
+                    while self.column_position < next_stop {
+                        self.put_byte(b' ');
+                    }
+                }
+            }
+            BACKSPACE => {
+                // Erase the previous cell on this line; at column 0 there is
+                // nothing to erase, so this is a no-op
+                if self.column_position > 0 {
+                    self.column_position -= 1;
+                    let (row, col) = (self.row_position, self.column_position);
+                    self.clear_cell(row, col);
+                }
+            }
             byte => {
                 // Bounds check: ensure we don't write beyond screen width
                 if self.column_position >= BUFFER_WIDTH {
                     self.new_line();
                 }
 
-                // Always write to the last row (bottom of screen)
-                // Row is guaranteed to be in bounds: BUFFER_HEIGHT - 1 is always < BUFFER_HEIGHT
-                let row = BUFFER_HEIGHT - 1;
+                // Row is always in bounds: set_cursor_position clamps it and
+                // new_line never moves past the bottom of the scroll region
+                let row = self.row_position;
                 let col = self.column_position;
 
                 // Column is now guaranteed to be in bounds after new_line() check above
                 let color_code = self.color_code;
-                self.buffer.chars[row][col].write(ScreenChar {
+                self.put_cell(row, col, ScreenChar {
                     ascii_character: byte,
                     color_code,
                 });
@@ -119,129 +758,3135 @@ impl Writer {
         }
     }
 
+    /// Writes a single character, translated to its Code Page 437 glyph.
+    ///
+    /// Newline, carriage return, tab and backspace are interpreted. Other
+    /// control characters are shown as `■` (0xfe), and characters CP437 has no
+    /// glyph for as the fallback glyph; see `set_fallback_glyph`.
+    pub fn write_char(&mut self, c: char) {
+        self.put_char(c);
+        self.present();
+        self.update_hardware_cursor();
+    }
+
+    /// Writes a string one character at a time; see `write_char`.
+    ///
+    /// With word wrap enabled, see `set_word_wrap`.
     pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                _ => self.write_byte(0xfe),
+        if !self.word_wrap {
+            for c in s.chars() {
+                self.put_char(c);
+            }
+        } else {
+            // A word continues one left by a previous write unless the
+            // cursor is at a line start or follows a blank
+            let col = self.column_position.min(BUFFER_WIDTH);
+            let word_start =
+                col == 0 || self.shadow[self.row_position][col - 1].ascii_character == b' ';
+
+            for step in WordWrap::new(s, BUFFER_WIDTH, self.tab_width, col, word_start) {
+                match step {
+                    Wrapped::Break => self.new_line(),
+                    Wrapped::Char(c) => self.put_char(c),
+                }
             }
         }
+        // Flush and sync the hardware cursor once per string rather than per
+        // character
+        self.present();
+        self.update_hardware_cursor();
     }
 
-    fn new_line(&mut self) {
-        // Scroll all rows up by one, starting from row 1 (row 0 gets overwritten)
-        // Bounds: row ranges from 1 to BUFFER_HEIGHT-1, so row-1 ranges from 0 to BUFFER_HEIGHT-2
-        // Both are valid indices in the [0..BUFFER_HEIGHT) range
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                // row - 1 is safe: when row = 1, row - 1 = 0 (valid)
-                // when row = BUFFER_HEIGHT - 1, row - 1 = BUFFER_HEIGHT - 2 (valid)
-                self.buffer.chars[row - 1][col].write(character);
-            }
+    /// Writes `s` like `write_string`, but reveals it one character at a time,
+    /// busy-waiting `cycles_per_char` TSC cycles after each one.
+    ///
+    /// Each character is flushed to the screen before the delay, regardless of
+    /// auto-flush. The delay is only approximate; see the `time` module.
+    ///
+    /// This keeps the writer borrowed for the whole string, so calling it
+    /// through `WRITER.lock()` holds the global lock (and blocks every other
+    /// printer) until the last character is out. For long text, lock per
+    /// character instead:
+    ///
+    /// ```ignore
+    /// for c in s.chars() {
+    ///     WRITER.lock().write_char(c);
+    ///     time::busy_wait_cycles(cycles_per_char);
+    /// }
+    /// ```
+    pub fn write_string_delayed(&mut self, s: &str, cycles_per_char: u64) {
+        for c in s.chars() {
+            self.put_char(c);
+            self.flush();
+            self.update_hardware_cursor();
+            time::busy_wait_cycles(cycles_per_char);
+        }
+    }
+
+    /// Translates and writes one character without syncing the hardware cursor.
+    fn put_char(&mut self, c: char) {
+        let byte = match c {
+            '\n' | '\r' | '\t' | '\u{8}' => c as u8,
+            _ => self.glyph(c),
+        };
+        self.put_byte(byte);
+    }
+
+    /// Maps a character to the CP437 glyph this writer displays for it; see
+    /// `set_fallback_glyph`.
+    fn glyph(&self, c: char) -> u8 {
+        match cp437::from_char(c) {
+            Some(byte) => byte,
+            None if c.is_control() => 0xfe,
+            None => self.fallback_glyph,
+        }
+    }
+
+    /// Moves the blinking VGA hardware cursor to the writer's cursor position.
+    fn update_hardware_cursor(&self) {
+        use x86_64::instructions::port::Port;
+
+        // A writer drawing off-screen has no cursor to show
+        if !core::ptr::eq(&*self.buffer, VGA_BUFFER_ADDR as *const Buffer) {
+            return;
+        }
+
+        let col = self.column_position.min(BUFFER_WIDTH - 1);
+        let position = (self.row_position * BUFFER_WIDTH + col) as u16;
+        let mut index: Port<u8> = Port::new(CRTC_INDEX);
+        let mut data: Port<u8> = Port::new(CRTC_DATA);
+
+        // SAFETY: 0x3D4/0x3D5 are the CRTC index/data ports in color text mode.
+        // Registers 0x0E/0x0F only hold the cursor location, and `position` is
+        // always within the current text mode.
+        unsafe {
+            index.write(CRTC_CURSOR_LOCATION_HIGH);
+            data.write((position >> 8) as u8);
+            index.write(CRTC_CURSOR_LOCATION_LOW);
+            data.write((position & 0xff) as u8);
+        }
+    }
+
+    /// Restricts scrolling to rows `top..=bottom` (like the VT100 `DECSTBM`).
+    ///
+    /// Rows outside the region are left alone by newlines and scrolling, which
+    /// allows fixed headers and status bars. The cursor is clamped into the
+    /// region. Requests that do not satisfy `top < bottom < height()` are
+    /// ignored.
+    pub fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        if top >= bottom || bottom >= self.height {
+            return;
+        }
+        self.scroll_top = top;
+        self.scroll_bottom = bottom;
+        self.row_position = self.row_position.clamp(self.first_text_row(), bottom);
+        self.update_hardware_cursor();
+    }
+
+    /// Resets the scroll region to the full screen.
+    pub fn clear_scroll_region(&mut self) {
+        self.scroll_top = 0;
+        self.scroll_bottom = self.height - 1;
+    }
+
+    /// Number of rows in the current text mode.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Switches the display to `mode` and resizes the writer to match.
+    ///
+    /// The scroll region is reset, the title bar and status line removed, any
+    /// history view left and the screen cleared, leaving the cursor at the
+    /// top-left corner.
+    pub fn set_text_mode(&mut self, mode: TextMode) {
+        vga_mode::set_text_mode(mode);
+        self.height = mode.rows();
+        self.title_active = false;
+        self.status_active = false;
+        self.view_offset = 0;
+        self.clear_scroll_region();
+        self.clear_screen();
+    }
+
+    /// First row regular output may use: the top of the scroll region, moved
+    /// down past row 0 while a title is shown.
+    fn first_text_row(&self) -> usize {
+        if self.title_active {
+            self.scroll_top.max(1)
+        } else {
+            self.scroll_top
+        }
+    }
+
+    /// Last row regular output may use: the bottom of the scroll region, moved
+    /// up past the last row while a status line is shown.
+    fn last_text_row(&self) -> usize {
+        if self.status_active {
+            self.scroll_bottom.min(self.height - 2)
+        } else {
+            self.scroll_bottom
         }
-        self.clear_row(BUFFER_HEIGHT - 1);
+    }
+
+    /// Blanks the scroll region in the current color and homes the cursor.
+    ///
+    /// Every cell is overwritten with a space; nothing is read back and nothing
+    /// is allocated. The cursor moves to the start of the first row, so output
+    /// then runs top-down. A title bar on row 0 and rows outside the scroll
+    /// region are preserved.
+    pub fn clear_screen(&mut self) {
+        let first_row = self.first_text_row();
+        for row in first_row..=self.last_text_row() {
+            self.clear_row(row);
+        }
+        self.row_position = first_row;
         self.column_position = 0;
+        self.present();
+        self.update_hardware_cursor();
     }
 
-    fn clear_row(&mut self, row: usize) {
-        // Note: This function assumes row is in bounds. It's only called internally
-        // with BUFFER_HEIGHT - 1, which is guaranteed to be valid.
-        // For defensive programming, we could add a bounds check here, but it would
-        // add runtime overhead. Since this is only called from new_line() with a
-        // constant value, the bounds are guaranteed at compile time.
+    /// Writes a prepared row of cells to `row` in a single pass.
+    ///
+    /// Up to `BUFFER_WIDTH` cells are copied from `cells`; any extra cells are
+    /// ignored. If `cells` is shorter than the screen width, the rest of the row
+    /// is padded with blanks in the current color. This is the primitive for
+    /// rendering whole lines (e.g. from a back buffer or a pager) and avoids the
+    /// per-character overhead of `write_byte`.
+    ///
+    /// Rows outside the buffer are ignored. The cursor is not moved.
+    pub fn blit_row(&mut self, row: usize, cells: &[ScreenChar]) {
+        if row >= self.height {
+            return;
+        }
+
         let blank = ScreenChar {
             ascii_character: b' ',
             color_code: self.color_code,
         };
         for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+            let cell = cells.get(col).copied().unwrap_or(blank);
+            self.put_cell(row, col, cell);
         }
+        self.present();
     }
-}
 
-impl fmt::Write for Writer {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.write_string(s);
-        Ok(())
+    /// Computes a 64-bit FNV-1a hash over every cell on screen.
+    ///
+    /// Both the character byte and the attribute byte of each `ScreenChar` are
+    /// hashed, row by row, so any change to a glyph or its color alters the
+    /// result. Golden tests can compare a single value for a known render
+    /// instead of checking all 2000 cells. The shadow buffer is hashed, so
+    /// changes not yet flushed are included.
+    pub fn screen_hash(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let mut hash = FNV_OFFSET_BASIS;
+        for row in 0..self.height {
+            for col in 0..BUFFER_WIDTH {
+                let cell = self.shadow[row][col];
+                for byte in [cell.ascii_character, cell.color_code.0] {
+                    hash ^= byte as u64;
+                    hash = hash.wrapping_mul(FNV_PRIME);
+                }
+            }
+        }
+        hash
     }
-}
 
-use spin::Mutex;
+    /// Draws a vertical bar chart with half-cell resolution.
+    ///
+    /// Each entry of `values` (a percentage in `0..=100`, larger values are
+    /// clamped) becomes one column starting at `col`, with bars growing upward
+    /// from the bottom of the `height`-row region whose top row is `row`. A bar
+    /// is `value * height * 2 / 100` half-cells tall (rounded to nearest),
+    /// rendered with the CP437 full block (0xDB) and, for an odd half, the lower
+    /// half block (0xDC) on top. Cells above the bar are blanked.
+    ///
+    /// Cells falling outside the screen are clipped. The cursor is not moved.
+    pub fn draw_bar_chart(
+        &mut self,
+        row: usize,
+        col: usize,
+        height: usize,
+        values: &[u8],
+        color: ColorCode,
+    ) {
+        const FULL_BLOCK: u8 = 0xdb;
+        const LOWER_HALF_BLOCK: u8 = 0xdc;
 
-/// Global VGA text buffer writer.
-///
-/// This static is initialized at compile time, but the actual VGA buffer access
-/// only occurs when `lock()` is called. The Writer::new() function creates a pointer
-/// to 0xb8000, but doesn't dereference it until write operations occur.
-///
-/// # Safety
-///
-/// Safe to use because:
-/// 1. The VGA buffer at 0xb8000 is always available in x86_64 bootloader context
-/// 2. spin::Mutex provides synchronization (no heap allocation required)
-/// 3. First access happens in kernel_main after bootloader has set up memory
-/// 4. All buffer accesses use Volatile<T> to prevent compiler optimizations
-pub static WRITER: Mutex<Writer> = Mutex::new(Writer::new());
+        for (i, &value) in values.iter().enumerate() {
+            let x = col + i;
+            if x >= BUFFER_WIDTH {
+                break;
+            }
 
-/// Panic-safe function to write directly to VGA buffer without acquiring the lock.
-///
-/// This function bypasses the Mutex to avoid deadlock if a panic occurs while
-/// the WRITER lock is held. It directly accesses the VGA buffer at 0xb8000.
-///
-/// # Safety
-///
-/// This function is unsafe because:
-/// 1. It bypasses the Mutex synchronization
-/// 2. It directly accesses the VGA buffer without lock protection
+            let percent = value.min(100) as usize;
+            let half_cells = (percent * height * 2 + 50) / 100;
+
+            // Walk from the bottom cell of the bar region upward
+            for level in 0..height {
+                let y = row + height - 1 - level;
+                if y >= self.height {
+                    continue;
+                }
+                let ascii_character = match half_cells.saturating_sub(level * 2) {
+                    0 => b' ',
+                    1 => LOWER_HALF_BLOCK,
+                    _ => FULL_BLOCK,
+                };
+                self.put_cell(y, x, ScreenChar {
+                    ascii_character,
+                    color_code: color,
+                });
+            }
+        }
+        self.present();
+    }
+
+    /// Blanks a rectangular region using the writer's default color.
+    ///
+    /// Unlike drawing operations, this ignores the active drawing color set via
+    /// `set_color`, so erasing a popup or window restores the normal screen
+    /// background. The region is clipped to the screen bounds. The cursor is
+    /// not moved.
+    pub fn clear_region(&mut self, row: usize, col: usize, width: usize, height: usize) {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.default_color_code,
+        };
+        let row_end = row.saturating_add(height).min(self.height);
+        let col_end = col.saturating_add(width).min(BUFFER_WIDTH);
+        for y in row..row_end {
+            for x in col..col_end {
+                self.put_cell(y, x, blank);
+            }
+        }
+        self.present();
+    }
+
+    /// Draws a single-line CP437 border (`┌─┐`, `│`, `└─┘`) in the current color.
+    ///
+    /// The box's top-left corner is at `(row, col)` and `width`/`height`
+    /// include the border. The interior is left untouched. Parts falling
+    /// outside the screen are clipped; boxes smaller than 2x2 are ignored.
+    /// The cursor is not moved.
+    pub fn draw_box(&mut self, row: usize, col: usize, width: usize, height: usize) {
+        self.draw_border(row, col, width, height, &SINGLE_BORDER, false);
+        self.present();
+    }
+
+    /// Like `draw_box`, but also blanks the interior in the current color.
+    pub fn draw_filled_box(&mut self, row: usize, col: usize, width: usize, height: usize) {
+        self.draw_border(row, col, width, height, &SINGLE_BORDER, true);
+        self.present();
+    }
+
+    /// Like `draw_box`, but with the double-line glyphs (`╔═╗`, `║`, `╚═╝`).
+    pub fn draw_double_box(&mut self, row: usize, col: usize, width: usize, height: usize) {
+        self.draw_border(row, col, width, height, &DOUBLE_BORDER, false);
+        self.present();
+    }
+
+    /// Draws a border with `glyphs` into the shadow buffer, optionally
+    /// blanking the interior. See `draw_box`.
+    fn draw_border(
+        &mut self,
+        row: usize,
+        col: usize,
+        width: usize,
+        height: usize,
+        glyphs: &BorderGlyphs,
+        fill: bool,
+    ) {
+        if width < 2 || height < 2 || row >= self.height || col >= BUFFER_WIDTH {
+            return;
+        }
+        let color_code = self.color_code;
+        let cell = |ascii_character| ScreenChar {
+            ascii_character,
+            color_code,
+        };
+        let right = col.saturating_add(width - 1);
+        let bottom = row.saturating_add(height - 1);
+
+        self.put_cell(row, col, cell(glyphs.top_left));
+        self.put_cell(row, right, cell(glyphs.top_right));
+        self.put_cell(bottom, col, cell(glyphs.bottom_left));
+        self.put_cell(bottom, right, cell(glyphs.bottom_right));
+        for x in col + 1..right.min(BUFFER_WIDTH) {
+            self.put_cell(row, x, cell(glyphs.horizontal));
+            self.put_cell(bottom, x, cell(glyphs.horizontal));
+        }
+        for y in row + 1..bottom.min(self.height) {
+            self.put_cell(y, col, cell(glyphs.vertical));
+            self.put_cell(y, right, cell(glyphs.vertical));
+            if fill {
+                for x in col + 1..right.min(BUFFER_WIDTH) {
+                    self.put_cell(y, x, cell(b' '));
+                }
+            }
+        }
+    }
+
+    /// Lays out `items` in a grid of `columns` equal-width columns, like `ls`.
+    ///
+    /// Each column is `BUFFER_WIDTH / columns` cells wide. Items fill the grid
+    /// row by row. Each one is truncated to leave at least one blank cell
+    /// before the next column. Control characters such as `\n` and `\t`
+    /// would break the grid, so they are drawn as a block (0xFE) instead.
+    /// The grid always starts at the beginning of a line and ends with a
+    /// newline. Requests for zero columns, or more columns than the screen
+    /// is wide, are clamped.
+    pub fn write_columns(&mut self, items: &[&str], columns: usize) {
+        if items.is_empty() {
+            return;
+        }
+
+        let columns = columns.clamp(1, BUFFER_WIDTH);
+        let width = BUFFER_WIDTH / columns;
+        let max_len = if width > 1 { width - 1 } else { width };
+
+        if self.column_position != 0 {
+            self.new_line();
+        }
+
+        for (i, item) in items.iter().enumerate() {
+            let column = i % columns;
+            if column == 0 && i != 0 {
+                self.new_line();
+            }
+
+            // Pad up to this column's start; the previous item may be shorter
+            while self.column_position < column * width {
+                self.put_byte(b' ');
+            }
+
+            // Each character occupies one cell, so truncate by characters
+            for c in item.chars().take(max_len) {
+                self.put_byte(self.glyph(c));
+            }
+        }
+        self.new_line();
+        self.present();
+        self.update_hardware_cursor();
+    }
+
+    /// Iterates over the character bytes of each row on screen, top to bottom.
+    ///
+    /// Reads the VGA buffer itself, so the rows match what is displayed
+    /// (including a scrollback view) rather than unflushed output. Colors are
+    /// dropped and bytes are CP437, not UTF-8.
+    pub fn rows(&self) -> impl Iterator<Item = [u8; BUFFER_WIDTH]> + '_ {
+        (0..self.height).map(move |row| {
+            let mut bytes = [b' '; BUFFER_WIDTH];
+            for (col, byte) in bytes.iter_mut().enumerate() {
+                *byte = self.buffer.read(row, col).ascii_character;
+            }
+            bytes
+        })
+    }
+
+    /// Writes every non-blank row on screen to the serial port.
+    ///
+    /// Trailing blanks are trimmed and bytes outside printable ASCII are sent
+    /// as `.`, so the host sees plain text. Handy for snapshotting the screen
+    /// from tests.
+    pub fn dump_to_serial(&self) {
+        let mut serial = SERIAL1.lock();
+        serial.init();
+        for row in self.rows() {
+            let Some(end) = row.iter().rposition(|&byte| byte != b' ') else {
+                continue;
+            };
+            for &byte in &row[..=end] {
+                serial.send(if (0x20..=0x7e).contains(&byte) { byte } else { b'.' });
+            }
+            serial.send(b'\n');
+        }
+    }
+
+    /// Prints `data` as a classic hex dump, 16 bytes per line.
+    ///
+    /// Each line reads `ADDR: XX XX ... XX  |ascii|`, where `ADDR` is the
+    /// address of the line's first byte counting from `base_addr`, in at least
+    /// eight hex digits. Bytes outside printable ASCII show as `.` in the
+    /// gutter. Output uses the current color, starts on a fresh line and
+    /// scrolls like regular text. An empty slice prints nothing.
+    pub fn hex_dump(&mut self, data: &[u8], base_addr: usize) {
+        const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+        const BYTES_PER_LINE: usize = 16;
+
+        if data.is_empty() {
+            return;
+        }
+        if self.column_position != 0 {
+            self.new_line();
+        }
+
+        for (i, line) in data.chunks(BYTES_PER_LINE).enumerate() {
+            let addr = base_addr.wrapping_add(i * BYTES_PER_LINE);
+            let digits = ((usize::BITS - addr.leading_zeros()).div_ceil(4) as usize).max(8);
+            for nibble in (0..digits).rev() {
+                self.put_byte(HEX_DIGITS[(addr >> (nibble * 4)) & 0xf]);
+            }
+            self.put_byte(b':');
+
+            for slot in 0..BYTES_PER_LINE {
+                self.put_byte(b' ');
+                match line.get(slot) {
+                    Some(&byte) => {
+                        self.put_byte(HEX_DIGITS[usize::from(byte >> 4)]);
+                        self.put_byte(HEX_DIGITS[usize::from(byte & 0xf)]);
+                    }
+                    // Pad a short last line so its gutter lines up
+                    None => {
+                        self.put_byte(b' ');
+                        self.put_byte(b' ');
+                    }
+                }
+            }
+
+            self.put_byte(b' ');
+            self.put_byte(b' ');
+            self.put_byte(b'|');
+            for &byte in line {
+                self.put_byte(if (0x20..=0x7e).contains(&byte) { byte } else { b'.' });
+            }
+            self.put_byte(b'|');
+            self.put_byte(b'\n');
+        }
+        self.present();
+        self.update_hardware_cursor();
+    }
+
+    /// Replaces the glyph at `(row, col)` while keeping the cell's existing color.
+    ///
+    /// Useful for in-place animations such as spinners. Out-of-range positions
+    /// are ignored. The cursor is not moved.
+    pub fn replace_char(&mut self, row: usize, col: usize, byte: u8) {
+        if row >= self.height || col >= BUFFER_WIDTH {
+            return;
+        }
+        let mut cell = self.shadow[row][col];
+        cell.ascii_character = byte;
+        self.put_cell(row, col, cell);
+        self.present();
+    }
+
+    /// Changes the color of the cell at `(row, col)` while keeping its glyph.
+    ///
+    /// Out-of-range positions are ignored. The cursor is not moved.
+    pub fn set_char_color(&mut self, row: usize, col: usize, color: ColorCode) {
+        if row >= self.height || col >= BUFFER_WIDTH {
+            return;
+        }
+        let mut cell = self.shadow[row][col];
+        cell.color_code = color;
+        self.put_cell(row, col, cell);
+        self.present();
+    }
+
+    /// Shows `text` centered on a title bar occupying row 0.
+    ///
+    /// The whole row is filled with `color` and the title is truncated to the
+    /// screen width. While a title is set, scrolling starts at row 1 so regular
+    /// output never overwrites it. Calling this again replaces the title.
+    pub fn set_title(&mut self, text: &str, color: ColorCode) {
+        let len = text.chars().count().min(BUFFER_WIDTH);
+        let start = (BUFFER_WIDTH - len) / 2;
+        let mut title = text.chars();
+        for col in 0..BUFFER_WIDTH {
+            let ascii_character = if col >= start && col < start + len {
+                title.next().map_or(b' ', |c| self.glyph(c))
+            } else {
+                b' '
+            };
+            self.put_cell(0, col, ScreenChar {
+                ascii_character,
+                color_code: color,
+            });
+        }
+        self.present();
+        self.title_active = true;
+        if self.row_position == 0 {
+            self.row_position = 1;
+            self.update_hardware_cursor();
+        }
+    }
+
+    /// Removes the title bar, blanking row 0 and returning it to the scroll area.
+    pub fn clear_title(&mut self) {
+        if self.title_active {
+            self.title_active = false;
+            self.clear_region(0, 0, BUFFER_WIDTH, 1);
+        }
+    }
+
+    /// Shows `s` on a status line occupying the last row.
+    ///
+    /// The whole row is filled with black on light gray and the text is
+    /// truncated to the screen width. While a status line is set, scrolling
+    /// stops one row above it, so regular output never overwrites it. Calling
+    /// this again replaces the text.
+    pub fn set_status_line(&mut self, s: &str) {
+        let color_code = ColorCode::new(Color::Black, Color::LightGray);
+        let row = self.height - 1;
+        let mut text = s.chars();
+        for col in 0..BUFFER_WIDTH {
+            self.put_cell(row, col, ScreenChar {
+                ascii_character: text.next().map_or(b' ', |c| self.glyph(c)),
+                color_code,
+            });
+        }
+        self.present();
+        self.status_active = true;
+        if self.row_position > self.last_text_row() {
+            self.row_position = self.last_text_row();
+            self.update_hardware_cursor();
+        }
+    }
+
+    /// Removes the status line, blanking the last row and returning it to the
+    /// scroll area.
+    pub fn clear_status_line(&mut self) {
+        if self.status_active {
+            self.status_active = false;
+            self.clear_region(self.height - 1, 0, BUFFER_WIDTH, 1);
+        }
+    }
+
+    /// Writes `s` horizontally centered on `row` in the current color.
+    ///
+    /// Every character occupies one cell after CP437 translation, so the
+    /// width is the number of characters. The rest of the row is blanked on
+    /// both sides, so nothing of a longer line written there before remains;
+    /// text wider than the screen starts at column 0 and is truncated. The
+    /// cursor is not moved; see `write_at`.
+    pub fn write_centered(&mut self, row: usize, s: &str) {
+        let width = s.chars().count().min(BUFFER_WIDTH);
+        let start = (BUFFER_WIDTH - width) / 2;
+        for col in (0..start).chain(start + width..BUFFER_WIDTH) {
+            self.clear_cell(row, col);
+        }
+        self.write_at(row, start, s);
+    }
+
+    /// Writes `s` at `(row, col)` in the current color without moving the cursor.
+    ///
+    /// See `write_at_colored`.
+    pub fn write_at(&mut self, row: usize, col: usize, s: &str) {
+        let color = self.color_code;
+        self.write_at_colored(row, col, s, color);
+    }
+
+    /// Writes `s` at `(row, col)` in `color` without moving the cursor.
+    ///
+    /// Characters are translated to CP437 like `write_string`, but control
+    /// characters are not interpreted; they show as `■`. Text is truncated at
+    /// the right edge and rows past the bottom of the screen are ignored.
+    /// Meant for HUD elements such as clocks and status fields.
+    pub fn write_at_colored(&mut self, row: usize, col: usize, s: &str, color: ColorCode) {
+        if row >= self.height {
+            return;
+        }
+        for (x, c) in (col..BUFFER_WIDTH).zip(s.chars()) {
+            self.put_cell(row, x, ScreenChar {
+                ascii_character: self.glyph(c),
+                color_code: color,
+            });
+        }
+        self.present();
+    }
+
+    /// Renders `s` as large block letters using the 5x7 banner font.
+    ///
+    /// Each font pixel occupies one cell: set pixels are CP437 full blocks
+    /// (0xDB) and unset pixels are blanks, both in `color`. Glyphs are 5 cells
+    /// wide with one blank column between them and 7 rows tall, with the top-left
+    /// of the first glyph at `(row, col)`. Supports `A`-`Z`, `0`-`9` and space;
+    /// other characters are rendered as blanks. Output is clipped at the screen
+    /// edges. The cursor is not moved.
+    pub fn write_big(&mut self, row: usize, col: usize, s: &str, color: ColorCode) {
+        const FULL_BLOCK: u8 = 0xdb;
+        const ADVANCE: usize = font::GLYPH_WIDTH + 1;
+
+        for (i, c) in s.chars().enumerate() {
+            let x0 = col + i * ADVANCE;
+            if x0 >= BUFFER_WIDTH {
+                break;
+            }
+            let glyph = font::glyph(c).unwrap_or(&[0; font::GLYPH_HEIGHT]);
+            for (dy, bits) in glyph.iter().enumerate() {
+                for dx in 0..font::GLYPH_WIDTH {
+                    let lit = bits & (1 << (font::GLYPH_WIDTH - 1 - dx)) != 0;
+                    self.put_cell(row + dy, x0 + dx, ScreenChar {
+                        ascii_character: if lit { FULL_BLOCK } else { b' ' },
+                        color_code: color,
+                    });
+                }
+            }
+        }
+        self.present();
+    }
+
+    /// Returns how many columns `write_string` advances the cursor when writing
+    /// the single line `s` from column `start_col`.
+    ///
+    /// Nothing is written. Tabs advance to the writer's next tab stop counted
+    /// from the start of the row, and every other character takes one cell,
+    /// whether it is ASCII or translated to a CP437 glyph, so this can differ
+    /// from both `s.len()` and `s.chars().count()`. Wrapping at the right edge
+    /// is not simulated; see `wrapped_row_count` for that.
+    pub fn measure_width(&self, s: &str, start_col: usize) -> usize {
+        let end = s.chars().fold(start_col, |col, c| match c {
+            '\t' => (col / self.tab_width + 1) * self.tab_width,
+            _ => col + 1,
+        });
+        end - start_col
+    }
+
+    /// Computes how many rows `s` would occupy if word-wrapped at `width` columns.
+    ///
+    /// Nothing is written. Lines are split on `\n` (a trailing newline does not
+    /// add an empty row, and an empty string occupies no rows). Within a line,
+    /// words move to the next row when they would cross `width`, and words longer
+    /// than `width` are hard-broken. Each character counts as one cell and tabs
+    /// advance to the writer's next tab stop. The layout is computed by the same
+    /// code as `write_string` with word wrap enabled (see `set_word_wrap`), so
+    /// the two always agree. A `width` of zero is treated as one.
+    pub fn wrapped_row_count(&self, s: &str, width: usize) -> usize {
+        let breaks = WordWrap::new(s, width.max(1), self.tab_width, 0, true)
+            .filter(|step| *step == Wrapped::Break)
+            .count();
+        s.lines().count() + breaks
+    }
+
+    /// Blanks from the cursor to the bottom-right corner of the screen (ANSI `ESC[0J`).
+    ///
+    /// The cell under the cursor and the rest of its row are cleared, along with
+    /// every row below it. Blanks use the current color. A status line on the
+    /// last row is preserved. The cursor is not moved.
+    pub fn clear_to_end_of_screen(&mut self) {
+        let row = self.row_position;
+        for col in self.column_position.min(BUFFER_WIDTH)..BUFFER_WIDTH {
+            self.clear_cell(row, col);
+        }
+        let end = if self.status_active { self.height - 1 } else { self.height };
+        for y in row + 1..end {
+            for col in 0..BUFFER_WIDTH {
+                self.clear_cell(y, col);
+            }
+        }
+        self.present();
+    }
+
+    /// Blanks from the top-left corner of the screen up to and including the
+    /// cursor (ANSI `ESC[1J`).
+    ///
+    /// Blanks use the current color. A title bar on row 0 is preserved. The
+    /// cursor is not moved.
+    pub fn clear_to_start_of_screen(&mut self) {
+        let row = self.row_position;
+        for y in self.first_text_row()..row {
+            for col in 0..BUFFER_WIDTH {
+                self.clear_cell(y, col);
+            }
+        }
+        let last_col = self.column_position.min(BUFFER_WIDTH - 1);
+        for col in 0..=last_col {
+            self.clear_cell(row, col);
+        }
+        self.present();
+    }
+
+    /// Blanks a single cell using the current color.
+    fn clear_cell(&mut self, row: usize, col: usize) {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        self.put_cell(row, col, blank);
+    }
+
+    /// Fills the screen with a color-bar test pattern.
+    ///
+    /// All rows but the last show eight equal-width vertical bars of the colors
+    /// `Black` through `LightGray`, each drawn as full blocks with that color as
+    /// both foreground and background. The last row cycles through all 16
+    /// colors as full-block foregrounds on black, so the bright colors are
+    /// visible even with blink enabled. The cursor is not moved.
+    pub fn draw_test_pattern(&mut self) {
+        const FULL_BLOCK: u8 = 0xdb;
+        const COLORS: [Color; 16] = Color::ALL;
+
+        let bar_width = BUFFER_WIDTH / 8;
+        for row in 0..self.height - 1 {
+            for col in 0..BUFFER_WIDTH {
+                let color = COLORS[(col / bar_width).min(7)];
+                self.put_cell(row, col, ScreenChar {
+                    ascii_character: FULL_BLOCK,
+                    color_code: ColorCode::new(color, color),
+                });
+            }
+        }
+
+        let swatch_width = BUFFER_WIDTH / COLORS.len();
+        for col in 0..BUFFER_WIDTH {
+            let color = COLORS[(col / swatch_width).min(COLORS.len() - 1)];
+            self.put_cell(self.height - 1, col, ScreenChar {
+                ascii_character: FULL_BLOCK,
+                color_code: ColorCode::new(color, Color::Black),
+            });
+        }
+        self.present();
+    }
+
+    /// Writes `s` interpreting a small, safe subset of Markdown.
+    ///
+    /// - Lines starting with `# ` are headings, shown (without the marker) in
+    ///   white on the current background.
+    /// - `**bold**` spans use the bright variant of the current foreground.
+    /// - `` `code` `` spans are shown black on light gray.
+    ///
+    /// Markers without a closing counterpart on the same line, and everything
+    /// else, are written literally. Spans do not nest. The writer's color is
+    /// restored afterwards.
+    pub fn write_markdown(&mut self, s: &str) {
+        let normal = self.color_code;
+        let heading = ColorCode(normal.0 & 0xf0 | Color::White as u8);
+        let bold = ColorCode(normal.0 | 0x08);
+        let code = ColorCode::new(Color::Black, Color::LightGray);
+
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.write_byte(b'\n');
+            }
+
+            if let Some(title) = line.strip_prefix("# ") {
+                self.color_code = heading;
+                self.write_string(title);
+                self.color_code = normal;
+                continue;
+            }
+
+            let mut rest = line;
+            while !rest.is_empty() {
+                let span = if let Some(inner) = rest.strip_prefix("**") {
+                    inner.find("**").map(|end| (2, end, bold))
+                } else if let Some(inner) = rest.strip_prefix('`') {
+                    inner.find('`').map(|end| (1, end, code))
+                } else {
+                    None
+                };
+
+                if let Some((marker, end, color_code)) = span {
+                    self.color_code = color_code;
+                    self.write_string(&rest[marker..marker + end]);
+                    self.color_code = normal;
+                    rest = &rest[2 * marker + end..];
+                    continue;
+                }
+
+                // Write literally up to the next potential marker, always
+                // consuming at least one whole character
+                let first = rest.chars().next().map_or(1, char::len_utf8);
+                let next = rest[first..]
+                    .find(['*', '`'])
+                    .map_or(rest.len(), |pos| pos + first);
+                self.write_string(&rest[..next]);
+                rest = &rest[next..];
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.advance_line();
+        if self.flush_on_newline {
+            self.flush();
+        }
+    }
+
+    /// Moves the cursor to the start of the next line, scrolling, wrapping or
+    /// clamping at the bottom of the scroll region.
+    fn advance_line(&mut self) {
+        self.column_position = 0;
+
+        // Above the bottom of the scroll region, just move the cursor down
+        let bottom = self.last_text_row();
+        if self.row_position < bottom {
+            self.row_position += 1;
+            // With line fill, give the new line a uniform background
+            if self.line_fill {
+                self.clear_row(self.row_position);
+            }
+            return;
+        }
+
+        match self.overflow {
+            OverflowBehavior::Scroll => {}
+            // Reuse the region's top row, or the bottom row itself
+            OverflowBehavior::Wrap => {
+                self.row_position = self.first_text_row();
+                self.clear_row(self.row_position);
+                return;
+            }
+            OverflowBehavior::Clamp => {
+                self.clear_row(bottom);
+                return;
+            }
+        }
+
+        // On the bottom row, scroll the rows of the region up by one (its top
+        // row gets overwritten). With a title bar, row 0 is reserved, so a
+        // region starting at row 0 scrolls from row 1 instead.
+        // Only the shadow buffer moves here; every row that shifted is marked
+        // dirty and reaches the screen with the next flush.
+        let top = self.first_text_row();
+        self.push_history(top);
+        self.shadow.copy_within(top + 1..=bottom, top);
+        self.dirty |= (u64::MAX >> (63 - bottom)) & !((1 << top) - 1);
+        self.clear_row(bottom);
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        // Note: This function assumes row is in bounds. It's only called internally
+        // with the bottom of the scroll region, the cursor row or rows below
+        // the screen height, all of which are always valid.
+        // For defensive programming, we could add a bounds check here, but it would
+        // add runtime overhead, and callers never pass an unchecked row.
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        self.shadow[row] = [blank; BUFFER_WIDTH];
+        self.dirty |= 1 << row;
+    }
+
+    /// Writes a single cell to the shadow buffer, silently ignoring positions
+    /// outside the screen.
+    fn put_cell(&mut self, row: usize, col: usize, cell: ScreenChar) {
+        if row < self.height && col < BUFFER_WIDTH {
+            self.shadow[row][col] = cell;
+            self.dirty |= 1 << row;
+        }
+    }
+
+    /// Flushes the shadow buffer if auto-flush is enabled.
+    fn present(&mut self) {
+        if self.auto_flush {
+            self.flush();
+        }
+    }
+}
+
+/// Configures a `Writer` before it is created.
 ///
-/// However, it's safe to use in a panic handler because:
-/// 1. Panics are single-threaded (no concurrent access)
-/// 2. We're already in a panic state, so avoiding deadlock is more important
-/// 3. The VGA buffer at 0xb8000 is always valid in x86_64 bootloader context
+/// All setters are `const fn`, so a configuration can live in a `const`. The
+/// final `build()` cannot run in const context because it takes a reference to
+/// the VGA buffer. The global `WRITER` is built with the default configuration
+/// on first use; to configure it, replace the writer at runtime, moving the
+/// scrollback history over to the new one:
 ///
-/// # Arguments
+/// ```ignore
+/// let mut writer = WRITER.lock();
+/// let history = writer.take_history();
+/// *writer = WriterBuilder::new()
+///     .foreground(Color::LightGreen)
+///     .background(Color::Black)
+///     .tab_width(4)
+///     .build();
+/// writer.set_history(history);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct WriterBuilder {
+    foreground: Color,
+    background: Color,
+    tab_width: usize,
+    overflow: OverflowBehavior,
+}
+
+// Configuration API for callers; `WRITER` uses the defaults
+#[allow(dead_code)]
+impl WriterBuilder {
+    /// Starts from the default configuration: yellow on black, tab stops every
+    /// 8 columns and scrolling enabled.
+    pub const fn new() -> WriterBuilder {
+        WriterBuilder {
+            foreground: Color::Yellow,
+            background: Color::Black,
+            tab_width: DEFAULT_TAB_WIDTH,
+            overflow: OverflowBehavior::Scroll,
+        }
+    }
+
+    pub const fn foreground(mut self, color: Color) -> WriterBuilder {
+        self.foreground = color;
+        self
+    }
+
+    pub const fn background(mut self, color: Color) -> WriterBuilder {
+        self.background = color;
+        self
+    }
+
+    /// Sets the distance between tab stops; clamped like `Writer::set_tab_width`.
+    pub const fn tab_width(mut self, width: usize) -> WriterBuilder {
+        self.tab_width = width;
+        self
+    }
+
+    /// Chooses whether a newline on the bottom row scrolls the screen (the
+    /// default) or wraps output back to the top row.
+    pub const fn auto_scroll(mut self, on: bool) -> WriterBuilder {
+        self.overflow = if on { OverflowBehavior::Scroll } else { OverflowBehavior::Wrap };
+        self
+    }
+
+    /// Chooses what a newline on the bottom row does; see `OverflowBehavior`.
+    /// Replaces the choice made with `auto_scroll`, and vice versa.
+    pub const fn overflow_behavior(mut self, behavior: OverflowBehavior) -> WriterBuilder {
+        self.overflow = behavior;
+        self
+    }
+
+    /// Creates a `Writer` with this configuration.
+    ///
+    /// The writer draws to the VGA text buffer at 0xb8000; see `Writer::new`
+    /// for why that is sound.
+    pub fn build(self) -> Writer<'static> {
+        // SAFETY: 0xb8000 is the standard VGA text buffer address in x86_64.
+        // This address is guaranteed to be valid and writable in the bootloader
+        // environment. We cast to *mut Buffer and immediately create a reference,
+        // which is safe because Buffer is a simple struct with no invariants
+        // that need to be maintained, and Buffer only accesses it with volatile operations.
+        let buffer = unsafe { &mut *(VGA_BUFFER_ADDR as *mut Buffer) };
+        self.build_with_buffer(buffer)
+    }
+
+    /// Builds a writer that draws into `buffer`; see `Writer::from_buffer`.
+    fn build_with_buffer(self, buffer: &mut Buffer) -> Writer<'_> {
+        let color_code = ColorCode::new(self.foreground, self.background);
+        Writer {
+            // Output starts on the bottom row and scrolls upward
+            row_position: BUFFER_HEIGHT - 1,
+            column_position: 0,
+            color_code,
+            default_color_code: color_code,
+            title_active: false,
+            status_active: false,
+            line_fill: false,
+            word_wrap: false,
+            transient_cr: false,
+            cr_pending: false,
+            tab_width: self.tab_width.clamp(1, MAX_TAB_WIDTH),
+            fallback_glyph: 0xfe,
+            scroll_top: 0,
+            scroll_bottom: BUFFER_HEIGHT - 1,
+            height: BUFFER_HEIGHT,
+            // The shadow starts blank; the first flush of each row replaces
+            // whatever the bootloader left there
+            shadow: [[ScreenChar {
+                ascii_character: b' ',
+                color_code,
+            }; BUFFER_WIDTH]; MAX_BUFFER_HEIGHT],
+            dirty: 0,
+            auto_flush: true,
+            flush_on_newline: false,
+            overflow: self.overflow,
+            history: None,
+            view_offset: 0,
+            buffer,
+        }
+    }
+}
+
+impl Default for WriterBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A bordered list of selectable items, e.g. for boot or config menus.
 ///
-/// * `s` - The string to write to the VGA buffer
-/// * `row` - The row to write to (0-24)
-/// * `col` - The starting column (0-79)
-/// * `color_code` - The color code to use
-pub unsafe fn panic_write_string(s: &str, row: usize, col: usize, color_code: ColorCode) {
-    // Bounds checking to prevent out-of-bounds access
-    if row >= BUFFER_HEIGHT || col >= BUFFER_WIDTH {
-        return;
+/// The menu is drawn at a fixed `(row, col)` with a single-line CP437 border,
+/// sized to fit its longest item. The selected item is highlighted with the
+/// writer's current colors inverted. Navigation wraps around at both ends, so
+/// the caller can map arrow keys directly onto `up()`/`down()`.
+// Widgets for callers; kernel_main does not draw any yet
+#[allow(dead_code)]
+pub struct Menu<'a> {
+    row: usize,
+    col: usize,
+    items: &'a [&'a str],
+    selected: usize,
+}
+
+#[allow(dead_code)]
+impl<'a> Menu<'a> {
+    pub fn new(row: usize, col: usize, items: &'a [&'a str]) -> Menu<'a> {
+        Menu {
+            row,
+            col,
+            items,
+            selected: 0,
+        }
     }
-    
-    // SAFETY: 0xb8000 is the standard VGA text buffer address.
-    // This is safe in panic context because we're single-threaded and
-    // the buffer is always available in bootloader context.
-    // The function is marked unsafe, so callers must ensure proper usage.
-    let buffer = &mut *(0xb8000 as *mut Buffer);
-    
-    let mut current_col = col;
-    for byte in s.bytes() {
-        if current_col >= BUFFER_WIDTH {
-            break;
-        }
-        
-        // Filter valid characters (same as Writer::write_string)
-        let char_byte = match byte {
-            0x20..=0x7e | b'\n' => byte,
-            _ => 0xfe, // Block character for invalid bytes
+
+    /// Index of the currently selected item.
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Moves the selection up one item, wrapping to the last item.
+    pub fn up(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+        self.selected = match self.selected {
+            0 => self.items.len() - 1,
+            n => n - 1,
         };
-        
-        if char_byte == b'\n' {
-            // Newline handling would require row management, but for panic
-            // messages we'll just stop to keep it simple
-            break;
-        }
-        
-        buffer.chars[row][current_col].write(ScreenChar {
-            ascii_character: char_byte,
-            color_code,
-        });
-        
-        current_col += 1;
+    }
+
+    /// Moves the selection down one item, wrapping to the first item.
+    pub fn down(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+        self.selected = (self.selected + 1) % self.items.len();
+    }
+
+    /// Renders the menu using the writer's current color.
+    ///
+    /// Cells falling outside the screen are clipped. The cursor is not moved.
+    pub fn draw(&self, writer: &mut Writer) {
+        let normal = writer.color_code;
+        let highlight = normal.inverted();
+        // One space of padding on each side of the longest item
+        let inner_width = self
+            .items
+            .iter()
+            .map(|item| item.chars().count())
+            .max()
+            .unwrap_or(0)
+            + 2;
+
+        writer.draw_border(
+            self.row,
+            self.col,
+            inner_width + 2,
+            self.items.len() + 2,
+            &SINGLE_BORDER,
+            false,
+        );
+
+        for (i, item) in self.items.iter().enumerate() {
+            let y = self.row + 1 + i;
+            let color_code = if i == self.selected { highlight } else { normal };
+            let mut chars = item.chars();
+            for offset in 0..inner_width {
+                // Offset 0 and trailing cells past the item are padding
+                let ascii_character = match offset {
+                    0 => b' ',
+                    _ => chars.next().map_or(b' ', |c| writer.glyph(c)),
+                };
+                writer.put_cell(y, self.col + 1 + offset, ScreenChar {
+                    ascii_character,
+                    color_code,
+                });
+            }
+        }
+        writer.present();
     }
 }
 
+/// An animated overlay drawn on top of a saved screen region.
+///
+/// On creation the cells under the region are captured. Each call to
+/// `frame()` first restores those cells and then runs the drawing closure, so
+/// successive frames never leave trails and the background is never corrupted.
+/// Dropping the overlay restores the region one last time.
+///
+/// Only the declared region is saved; frames should not draw outside it. The
+/// region is clipped to the screen. The snapshot is a fixed-size array, so no
+/// heap is needed.
+#[allow(dead_code)]
+pub struct Overlay<'a, 'w> {
+    writer: &'a mut Writer<'w>,
+    row: usize,
+    col: usize,
+    width: usize,
+    height: usize,
+    saved: [[ScreenChar; BUFFER_WIDTH]; MAX_BUFFER_HEIGHT],
+}
+
+#[allow(dead_code)]
+impl<'a, 'w> Overlay<'a, 'w> {
+    pub fn new(
+        writer: &'a mut Writer<'w>,
+        row: usize,
+        col: usize,
+        width: usize,
+        height: usize,
+    ) -> Overlay<'a, 'w> {
+        let row = row.min(writer.height);
+        let col = col.min(BUFFER_WIDTH);
+        let width = width.min(BUFFER_WIDTH - col);
+        let height = height.min(writer.height - row);
+
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: writer.color_code,
+        };
+        let mut saved = [[blank; BUFFER_WIDTH]; MAX_BUFFER_HEIGHT];
+        for (saved_row, shadow_row) in saved.iter_mut().zip(&writer.shadow[row..row + height]) {
+            saved_row[..width].copy_from_slice(&shadow_row[col..col + width]);
+        }
+
+        Overlay {
+            writer,
+            row,
+            col,
+            width,
+            height,
+            saved,
+        }
+    }
+
+    /// Restores the background and then draws one frame with `draw`.
+    ///
+    /// The restored background is not flushed on its own, so with auto-flush
+    /// enabled the screen goes straight from one frame to the next.
+    pub fn frame<F: FnOnce(&mut Writer<'w>)>(&mut self, draw: F) {
+        self.restore();
+        draw(self.writer);
+        self.writer.present();
+    }
+
+    /// Writes the saved cells back to the shadow buffer.
+    fn restore(&mut self) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.writer.put_cell(self.row + y, self.col + x, self.saved[y][x]);
+            }
+        }
+    }
+}
+
+impl Drop for Overlay<'_, '_> {
+    fn drop(&mut self) {
+        self.restore();
+        self.writer.present();
+    }
+}
+
+/// CP437 glyphs making up a box border.
+struct BorderGlyphs {
+    top_left: u8,
+    top_right: u8,
+    bottom_left: u8,
+    bottom_right: u8,
+    horizontal: u8,
+    vertical: u8,
+}
+
+const SINGLE_BORDER: BorderGlyphs = BorderGlyphs {
+    top_left: 0xda,
+    top_right: 0xbf,
+    bottom_left: 0xc0,
+    bottom_right: 0xd9,
+    horizontal: 0xc4,
+    vertical: 0xb3,
+};
+
+const DOUBLE_BORDER: BorderGlyphs = BorderGlyphs {
+    top_left: 0xc9,
+    top_right: 0xbb,
+    bottom_left: 0xc8,
+    bottom_right: 0xbc,
+    horizontal: 0xcd,
+    vertical: 0xba,
+};
+
+/// Backspace control byte; moves the cursor left and erases that cell.
+const BACKSPACE: u8 = 0x08;
+
+/// Number of rows kept in the scrollback history.
+const SCROLLBACK_ROWS: usize = 200;
+
+const DEFAULT_TAB_WIDTH: usize = 8;
+const MAX_TAB_WIDTH: usize = 16;
+
+/// Number of characters before the first space, tab or newline in `s`.
+fn word_len(s: &str) -> usize {
+    s.chars().take_while(|c| !is_separator(*c)).count()
+}
+
+/// Returns `true` for the characters that end a word.
+fn is_separator(c: char) -> bool {
+    matches!(c, ' ' | '\n' | '\r' | '\t')
+}
+
+/// One step of word-wrapped output, produced by `WordWrap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Wrapped {
+    /// Start a new line here; the text did not fit on the current one.
+    Break,
+    /// Output this character as usual. Explicit newlines arrive as
+    /// `Char('\n')`.
+    Char(char),
+}
+
+/// Lays out text for word wrap at `width` columns.
+///
+/// Yields the characters of the text, with a `Break` wherever a line has to
+/// be started early: before a word that would cross the right edge, before a
+/// character past the edge of an over-long word, and in place of a tab whose
+/// next stop is past the edge. A space falling on the edge is dropped. The
+/// column is tracked the way `Writer::put_byte` moves the cursor, so this
+/// drives both `write_string` and `wrapped_row_count`.
+struct WordWrap<'a> {
+    text: &'a str,
+    chars: core::str::CharIndices<'a>,
+    width: usize,
+    tab_width: usize,
+    col: usize,
+    /// Set when the next non-separator character begins a new word.
+    word_start: bool,
+    /// Character held back by a `Break` and returned next.
+    pending: Option<char>,
+}
+
+impl<'a> WordWrap<'a> {
+    /// Lays out `text` starting at column `col`. `word_start` tells whether
+    /// text at that position begins a word rather than continuing one.
+    fn new(text: &'a str, width: usize, tab_width: usize, col: usize, word_start: bool) -> Self {
+        WordWrap {
+            text,
+            chars: text.char_indices(),
+            width,
+            tab_width,
+            col,
+            word_start,
+            pending: None,
+        }
+    }
+
+    /// Moves the column past `c`, which is known to fit.
+    fn advance(&mut self, c: char) {
+        self.col = match c {
+            '\n' | '\r' => 0,
+            '\t' => (self.col / self.tab_width + 1) * self.tab_width,
+            '\u{8}' => self.col.saturating_sub(1),
+            _ => self.col + 1,
+        };
+    }
+}
+
+impl Iterator for WordWrap<'_> {
+    type Item = Wrapped;
+
+    fn next(&mut self) -> Option<Wrapped> {
+        if let Some(c) = self.pending.take() {
+            self.advance(c);
+            return Some(Wrapped::Char(c));
+        }
+        loop {
+            let (i, c) = self.chars.next()?;
+            let separator = is_separator(c);
+            let word_start = core::mem::replace(&mut self.word_start, separator);
+
+            if c == ' ' && self.col >= self.width {
+                // The next word starts a new line anyway
+                continue;
+            }
+            if c == '\t' && (self.col / self.tab_width + 1) * self.tab_width > self.width {
+                self.col = 0;
+                return Some(Wrapped::Break);
+            }
+            let printable = !separator && c != '\u{8}';
+            let breaks = printable
+                && self.col > 0
+                && (self.col >= self.width
+                    || (word_start && self.col + word_len(&self.text[i..]) > self.width));
+            if breaks {
+                self.col = 0;
+                self.pending = Some(c);
+                return Some(Wrapped::Break);
+            }
+            self.advance(c);
+            return Some(Wrapped::Char(c));
+        }
+    }
+}
+
+/// Maps a character to the CP437 glyph displayed for it.
+///
+/// Characters without a CP437 glyph, including control characters, become the
+/// block character `0xfe` (■).
+fn glyph_byte(c: char) -> u8 {
+    cp437::from_char(c).unwrap_or(0xfe)
+}
+
+impl Default for Writer<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Write for Writer<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_string(s);
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        Writer::write_char(self, c);
+        Ok(())
+    }
+}
+
+/// `fmt::Write` target that sends the same text to a `Writer` and a serial
+/// port.
+///
+/// The screen gets the text translated to CP437 as usual; the serial port
+/// gets the raw UTF-8 bytes, which a host terminal displays as-is.
+pub struct TeeWriter<'a, 'w> {
+    vga: &'a mut Writer<'w>,
+    /// `None` when the port was not initialized, so output skips it.
+    serial: Option<&'a mut SerialPort>,
+}
+
+impl<'a, 'w> TeeWriter<'a, 'w> {
+    /// Tees output to `vga` and `serial`. A serial port that has not been
+    /// initialized is silently skipped, so only the screen gets the text.
+    pub fn new(vga: &'a mut Writer<'w>, serial: &'a mut SerialPort) -> TeeWriter<'a, 'w> {
+        let serial = if serial.is_initialized() { Some(serial) } else { None };
+        TeeWriter { vga, serial }
+    }
+}
+
+impl fmt::Write for TeeWriter<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.vga.write_string(s);
+        if let Some(serial) = self.serial.as_deref_mut() {
+            for byte in s.bytes() {
+                serial.send(byte);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A one-cell spinner animation drawn in place at a fixed screen position.
+///
+/// Each `tick()` draws the next frame of `| / - \` using
+/// `Writer::replace_char`, so the cell keeps its color and surrounding text
+/// is never disturbed. The spinner does not hook the timer interrupt itself;
+/// callers drive the animation by calling `tick()`, for example from their
+/// work loop or after each `timer::sleep_ticks`.
+#[allow(dead_code)]
+pub struct Spinner {
+    row: usize,
+    col: usize,
+    frame: usize,
+    active: bool,
+}
+
+#[allow(dead_code)]
+impl Spinner {
+    const FRAMES: [u8; 4] = *b"|/-\\";
+
+    pub fn new(row: usize, col: usize) -> Spinner {
+        Spinner {
+            row,
+            col,
+            frame: 0,
+            active: false,
+        }
+    }
+
+    /// Activates the spinner, restarting from the first frame.
+    pub fn start(&mut self) {
+        self.frame = 0;
+        self.active = true;
+    }
+
+    /// Draws the current frame and advances to the next one.
+    ///
+    /// Does nothing while the spinner is stopped.
+    pub fn tick(&mut self, writer: &mut Writer) {
+        if !self.active {
+            return;
+        }
+        writer.replace_char(self.row, self.col, Self::FRAMES[self.frame]);
+        self.frame = (self.frame + 1) % Self::FRAMES.len();
+    }
+
+    /// Stops the spinner and blanks its cell.
+    pub fn stop(&mut self, writer: &mut Writer) {
+        self.active = false;
+        writer.replace_char(self.row, self.col, b' ');
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+use core::ptr::addr_of_mut;
+use spin::{Lazy, Mutex};
+
+/// Global VGA text buffer writer.
+///
+/// `Writer::new()` is not a `const fn`, so the writer is created on first use
+/// rather than at compile time. The first `lock()` builds it with the default
+/// configuration and a static scrollback `History`; nothing touches the VGA
+/// buffer before that.
+///
+/// # Safety
+///
+/// Safe to use because:
+/// 1. The VGA buffer at 0xb8000 is always available in x86_64 bootloader context
+/// 2. spin::Mutex provides synchronization (no heap allocation required)
+/// 3. First access happens in kernel_main after bootloader has set up memory
+/// 4. All buffer accesses are volatile to prevent compiler optimizations
+pub static WRITER: Lazy<Mutex<Writer<'static>>> = Lazy::new(|| {
+    static mut SCROLLBACK: History = History::new();
+
+    let mut writer = Writer::new();
+    // SAFETY: Lazy runs this closure at most once, so this is the only
+    // reference ever created to SCROLLBACK.
+    writer.set_history(Some(unsafe { &mut *addr_of_mut!(SCROLLBACK) }));
+    Mutex::new(writer)
+});
+
+/// Prints to the VGA text buffer through the global `WRITER`.
+///
+/// Equivalent to `std`'s `print!`, but renders onto the screen.
+///
+/// ```ignore
+/// print!("Booting");
+/// print!(" stage {} of {}", 1, 3);
+/// ```
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::vga_buffer::_print(format_args!($($arg)*)));
+}
+
+/// Prints to the VGA text buffer through the global `WRITER`, with a newline.
+///
+/// `println!()` with no arguments emits a single newline.
+///
+/// ```ignore
+/// println!();
+/// println!("Hello from Rust OS!");
+/// println!("{} + {} = {}", 1, 2, 1 + 2);
+/// ```
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+/// Prints to the VGA text buffer like `print!`, unless `WRITER` is locked.
+///
+/// Evaluates to `true` if the text was printed and `false` if it was
+/// dropped. It never waits for the lock, so interrupt handlers can use it
+/// even when the code they interrupted holds `WRITER`.
+///
+/// ```ignore
+/// if !try_print!(".") {
+///     DROPPED.fetch_add(1, Ordering::Relaxed);
+/// }
+/// ```
+#[macro_export]
+macro_rules! try_print {
+    ($($arg:tt)*) => ($crate::vga_buffer::_try_print(format_args!($($arg)*)));
+}
+
+/// When set, `print!` output is mirrored to the serial port.
+static MIRROR_TO_SERIAL: AtomicBool = AtomicBool::new(false);
+
+/// Mirrors everything printed with `print!`/`println!` to COM1 as well
+/// (off by default).
+///
+/// Saves duplicating each line with `serial_print!` during development.
+/// Output only reaches the serial port once it has been initialized with
+/// `serial::init`; until then the screen alone gets it.
+// A development aid, switched on by hand while debugging
+#[allow(dead_code)]
+pub fn mirror_to_serial(on: bool) {
+    MIRROR_TO_SERIAL.store(on, Ordering::Relaxed);
+}
+
+/// Backend for `print!`/`println!`; not meant to be called directly.
+///
+/// The lock is held only for the duration of one formatted write. `spin::Mutex`
+/// has no poisoning, so a panic inside a `Display` impl leaves the writer usable,
+/// and the panic handler writes lock-free so it cannot deadlock on this lock.
+/// Interrupts are disabled while the lock is held, so an interrupt handler
+/// that prints cannot spin on a lock held by the code it interrupted. Debug
+/// builds panic instead of deadlocking if that happens anyway.
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    // With interrupts off, nothing can release a held lock
+    debug_assert!(
+        interrupts::are_enabled() || !WRITER.is_locked(),
+        "print! with interrupts disabled while WRITER is locked"
+    );
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        // Neither writer's write_str ever fails, so the result carries no
+        // information
+        if MIRROR_TO_SERIAL.load(Ordering::Relaxed) {
+            let mut serial = SERIAL1.lock();
+            let _ = TeeWriter::new(&mut writer, &mut serial).write_fmt(args);
+        } else {
+            let _ = writer.write_fmt(args);
+        }
+    });
+}
+
+/// Backend for `try_print!`; not meant to be called directly.
+///
+/// The serial mirror is skipped, since the serial lock could be held too.
+#[doc(hidden)]
+pub fn _try_print(args: fmt::Arguments) -> bool {
+    use core::fmt::Write;
+
+    match WRITER.try_lock() {
+        Some(mut writer) => {
+            let _ = writer.write_fmt(args);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Lock-free `fmt::Write` target for the panic handler.
+///
+/// Writes straight to the VGA buffer at 0xb8000, bypassing the `WRITER`
+/// lock, so the panic handler cannot deadlock on a lock the panicking code
+/// held. It keeps its own cursor so formatted output can span several rows:
+/// text wraps at the right edge, `\n` starts a new row, and anything past the
+/// bottom of the screen is dropped. Characters are translated to CP437 like
+/// `Writer::write_string`.
+///
+/// Only the buffer is written; the CRTC registers are never touched, so the
+/// hardware cursor stays where `WRITER` left it if execution continues.
+pub struct PanicWriter {
+    row: usize,
+    col: usize,
+    color_code: ColorCode,
+    buffer: &'static mut Buffer,
+}
+
+impl PanicWriter {
+    /// Creates a writer whose output starts at `(row, col)`.
+    ///
+    /// # Safety
+    ///
+    /// This bypasses the `WRITER` lock, so it is only meant for the panic
+    /// handler (and tests holding the lock), where nothing else uses the VGA
+    /// buffer concurrently.
+    pub unsafe fn new(row: usize, col: usize, color_code: ColorCode) -> PanicWriter {
+        PanicWriter {
+            row,
+            col,
+            color_code,
+            // SAFETY: 0xb8000 is the standard VGA text buffer address; the
+            // caller guarantees no other code is using it concurrently.
+            buffer: &mut *(VGA_BUFFER_ADDR as *mut Buffer),
+        }
+    }
+}
+
+impl fmt::Write for PanicWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.row >= BUFFER_HEIGHT {
+                break;
+            }
+            if c == '\n' {
+                self.row += 1;
+                self.col = 0;
+                continue;
+            }
+            if self.col >= BUFFER_WIDTH {
+                self.row += 1;
+                self.col = 0;
+                if self.row >= BUFFER_HEIGHT {
+                    break;
+                }
+            }
+
+            self.buffer.write(self.row, self.col, ScreenChar {
+                ascii_character: glyph_byte(c),
+                color_code: self.color_code,
+            });
+            self.col += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `s` on a single row of the VGA buffer without taking the `WRITER`
+/// lock.
+///
+/// A minimal fallback for the panic path, starting at `(row, col)`. `row` is
+/// clamped to the last row, and text that would run past the right edge is
+/// cut off rather than wrapped, so nothing outside the buffer is ever
+/// written; a `col` past the edge writes nothing. Characters are translated
+/// to CP437 like `Writer::write_string`, and anything without a glyph,
+/// including control characters such as `\n`, shows as `■` (0xfe). Use
+/// `PanicWriter` for wrapped, formatted output.
+///
+/// # Safety
+///
+/// This bypasses the `WRITER` lock, so the caller must ensure nothing else
+/// uses the VGA buffer meanwhile: in the panic handler, interrupts must be
+/// disabled, which on a single CPU makes it the only code running.
+// An alternative to `PanicWriter` for fixed-position messages
+#[allow(dead_code)]
+pub unsafe fn panic_write_string(s: &str, row: usize, col: usize, color: ColorCode) {
+    // SAFETY: The caller guarantees exclusive access to the VGA buffer.
+    let writer = PanicWriter::new(row.min(BUFFER_HEIGHT - 1), col, color);
+    for (col, c) in (writer.col..BUFFER_WIDTH).zip(s.chars()) {
+        writer.buffer.write(writer.row, col, ScreenChar {
+            ascii_character: glyph_byte(c),
+            color_code: writer.color_code,
+        });
+    }
+}
+
+/// CRTC index and data ports (color text mode).
+pub(crate) const CRTC_INDEX: u16 = 0x3d4;
+pub(crate) const CRTC_DATA: u16 = 0x3d5;
+/// CRTC registers holding the high and low bytes of the cursor cell offset.
+const CRTC_CURSOR_LOCATION_HIGH: u8 = 0x0e;
+const CRTC_CURSOR_LOCATION_LOW: u8 = 0x0f;
+
+/// VGA attribute controller address/data port (index and data share 0x3C0 for writes).
+pub(crate) const ATTRIBUTE_CONTROLLER_INDEX: u16 = 0x3c0;
+/// VGA attribute controller data read port.
+pub(crate) const ATTRIBUTE_CONTROLLER_READ: u16 = 0x3c1;
+/// Input status register 1; reading it resets the attribute controller flip-flop.
+pub(crate) const INPUT_STATUS_1: u16 = 0x3da;
+/// DAC read-mode address port.
+const DAC_READ_INDEX: u16 = 0x3c7;
+/// DAC write-mode address port.
+const DAC_WRITE_INDEX: u16 = 0x3c8;
+/// DAC data port (three consecutive accesses: red, green, blue).
+const DAC_DATA: u16 = 0x3c9;
+/// Palette Address Source bit; must be set again after touching palette registers
+/// or the display stays blanked.
+pub(crate) const ATTRIBUTE_PAS: u8 = 0x20;
+
+/// Looks up which DAC entry a text-mode palette index (0-15) is routed to.
+///
+/// In text mode the 4-bit color in the attribute byte does not address the DAC
+/// directly. It first selects one of the 16 attribute controller palette
+/// registers, and the value stored there is the DAC index actually used. With
+/// the default BIOS setup these are 0-5, 20, 7 and 56-63, which is why
+/// "Brown" is DAC entry 20 rather than 6.
+fn dac_index_for(index: u8) -> u8 {
+    use x86_64::instructions::port::{Port, PortReadOnly};
+
+    let mut status: PortReadOnly<u8> = PortReadOnly::new(INPUT_STATUS_1);
+    let mut ac_index: Port<u8> = Port::new(ATTRIBUTE_CONTROLLER_INDEX);
+    let mut ac_read: PortReadOnly<u8> = PortReadOnly::new(ATTRIBUTE_CONTROLLER_READ);
+
+    // SAFETY: These are the standard VGA attribute controller ports. Reading
+    // 0x3DA puts the controller's flip-flop into "index" state so the following
+    // write to 0x3C0 selects a register. Re-setting PAS afterwards re-enables
+    // normal display output.
+    unsafe {
+        status.read();
+        ac_index.write(index & 0x0f);
+        let dac_index = ac_read.read();
+        status.read();
+        ac_index.write(ATTRIBUTE_PAS);
+        dac_index
+    }
+}
+
+/// Reprograms the RGB value shown for a text-mode palette index.
+///
+/// `index` is the 4-bit color from the attribute byte (the same value as a
+/// `Color` discriminant); indices of 16 and above are ignored. `red`, `green`
+/// and `blue` are 6-bit DAC intensities (0-63); higher bits are masked off.
+///
+/// The index is first resolved through the attribute controller to the DAC
+/// entry it is routed to (see `dac_index_for`), so this changes what every
+/// cell using that color looks like, e.g. making `Color::Blue` render as cyan.
+// Palette API for callers; the kernel itself keeps the BIOS palette
+#[allow(dead_code)]
+pub fn set_palette_color(index: u8, red: u8, green: u8, blue: u8) {
+    use x86_64::instructions::port::Port;
+
+    if index >= 16 {
+        return;
+    }
+
+    let dac_index = dac_index_for(index);
+    let mut dac_write_index: Port<u8> = Port::new(DAC_WRITE_INDEX);
+    let mut dac_data: Port<u8> = Port::new(DAC_DATA);
+
+    // SAFETY: Writing the DAC index to 0x3C8 followed by exactly three writes
+    // to 0x3C9 is the documented sequence for loading one DAC entry. It only
+    // affects display colors, not memory.
+    unsafe {
+        dac_write_index.write(dac_index);
+        dac_data.write(red & 0x3f);
+        dac_data.write(green & 0x3f);
+        dac_data.write(blue & 0x3f);
+    }
+}
+
+/// Reads back the 6-bit `(red, green, blue)` values for a text-mode palette index.
+///
+/// Returns `None` for indices of 16 and above.
+#[allow(dead_code)]
+pub fn palette_color(index: u8) -> Option<(u8, u8, u8)> {
+    use x86_64::instructions::port::Port;
+
+    if index >= 16 {
+        return None;
+    }
+
+    let dac_index = dac_index_for(index);
+    let mut dac_read_index: Port<u8> = Port::new(DAC_READ_INDEX);
+    let mut dac_data: Port<u8> = Port::new(DAC_DATA);
+
+    // SAFETY: Writing the DAC index to 0x3C7 followed by three reads from 0x3C9
+    // is the documented sequence for reading one DAC entry and has no side
+    // effects beyond advancing the DAC's internal pointer.
+    unsafe {
+        dac_read_index.write(dac_index);
+        let red = dac_data.read() & 0x3f;
+        let green = dac_data.read() & 0x3f;
+        let blue = dac_data.read() & 0x3f;
+        Some((red, green, blue))
+    }
+}
+
+/// Returns the character shown at `(row, col)`, for tests.
+#[cfg(test)]
+fn glyph_at(writer: &Writer, row: usize, col: usize) -> Option<u8> {
+    writer.read_cell(row, col).map(|(byte, _)| byte)
+}
+
+/// Asserts that the screen shows `text` starting at `(row, col)`, in `color`
+/// if one is given.
+#[cfg(test)]
+fn assert_text_at(
+    writer: &Writer,
+    row: usize,
+    col: usize,
+    text: &[u8],
+    color: Option<ColorCode>,
+) {
+    for (i, &byte) in text.iter().enumerate() {
+        match color {
+            Some(color) => assert_eq!(writer.read_cell(row, col + i), Some((byte, color))),
+            None => assert_eq!(glyph_at(writer, row, col + i), Some(byte)),
+        }
+    }
+}
+
+#[test_case]
+fn test_write_string_output() {
+    let s = "Some test string that fits on a single line";
+    let mut writer = WRITER.lock();
+    writer.write_string("\n");
+    writer.write_string(s);
+    let row = writer.row_position;
+    assert_text_at(&writer, row, 0, s.as_bytes(), None);
+}
+
+#[test_case]
+fn test_println_output() {
+    use x86_64::instructions::interrupts;
+
+    let s = "Some test string printed through println!";
+    // Start on a fresh line, so the string neither wraps nor scrolls mid-way
+    println!();
+    println!("{}", s);
+    interrupts::without_interrupts(|| {
+        let writer = WRITER.lock();
+        // The trailing newline moved the cursor one row past the string
+        let row = writer.row_position - 1;
+        assert_text_at(&writer, row, 0, s.as_bytes(), None);
+    });
+}
+
+#[test_case]
+fn test_color_code_round_trip() {
+    let code = ColorCode::new(Color::LightCyan, Color::Blue);
+    assert_eq!(code.to_u8(), 0x1b);
+    assert_eq!(ColorCode::from_u8(code.to_u8()), code);
+}
+
+#[test_case]
+fn test_scroll_flush_benchmark() {
+    use crate::serial_println;
+    use core::arch::x86_64::_rdtsc;
+    use core::fmt::Write;
+
+    const LINES: usize = 1000;
+    let mut writer = WRITER.lock();
+
+    // Every line flushed as it is written (the default)
+    // SAFETY: RDTSC is available on every x86_64 CPU and has no side effects.
+    let start = unsafe { _rdtsc() };
+    for i in 0..LINES {
+        let _ = writeln!(writer, "scroll benchmark line {}", i);
+    }
+    let immediate = unsafe { _rdtsc() } - start;
+
+    // The same output batched into a single flush
+    writer.set_auto_flush(false);
+    let start = unsafe { _rdtsc() };
+    for i in 0..LINES {
+        let _ = writeln!(writer, "scroll benchmark line {}", i);
+    }
+    writer.flush();
+    let batched = unsafe { _rdtsc() } - start;
+    writer.set_auto_flush(true);
+
+    serial_println!(
+        "{} lines: {} cycles auto-flushed, {} cycles batched",
+        LINES,
+        immediate,
+        batched
+    );
+
+    assert_eq!(writer.dirty, 0);
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            assert_eq!(writer.buffer.read(row, col), writer.shadow[row][col]);
+        }
+    }
+}
+
+#[test_case]
+fn test_scroll_region_keeps_header() {
+    let mut writer = WRITER.lock();
+    let header = [ScreenChar {
+        ascii_character: b'H',
+        color_code: ColorCode::new(Color::White, Color::Blue),
+    }; BUFFER_WIDTH];
+    writer.blit_row(0, &header);
+    writer.blit_row(1, &header);
+
+    writer.set_scroll_region(2, BUFFER_HEIGHT - 1);
+    for _ in 0..2 * BUFFER_HEIGHT {
+        writer.write_string("scrolling body line\n");
+    }
+    let (row, _) = writer.get_cursor_position();
+    writer.clear_scroll_region();
+
+    assert!(row >= 2);
+    for row in 0..2 {
+        for (col, &cell) in header.iter().enumerate() {
+            assert_eq!(writer.buffer.read(row, col), cell);
+        }
+    }
+}
+
+#[test_case]
+fn test_writer_builder_colors() {
+    let writer = WriterBuilder::new()
+        .foreground(Color::Cyan)
+        .background(Color::Blue)
+        .tab_width(4)
+        .build();
+    assert_eq!(writer.color_code, ColorCode::new(Color::Cyan, Color::Blue));
+    assert_eq!(writer.color_code.to_u8(), 0x13);
+    assert_eq!(writer.default_color_code, writer.color_code);
+    assert_eq!(writer.tab_width, 4);
+}
+
+#[test_case]
+fn test_80x50_addresses_last_row() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    // Resize without reprogramming the hardware
+    writer.height = TextMode::Mode80x50.rows();
+    writer.set_auto_flush(false);
+
+    let cell = ScreenChar {
+        ascii_character: b'#',
+        color_code: ColorCode::new(Color::White, Color::Black),
+    };
+    writer.put_cell(49, BUFFER_WIDTH - 1, cell);
+    writer.put_cell(50, 0, cell);
+
+    assert_eq!(writer.shadow[49][BUFFER_WIDTH - 1], cell);
+    assert_eq!(writer.dirty, 1 << 49);
+    writer.flush();
+    assert_eq!(glyph_at(&writer, 49, BUFFER_WIDTH - 1), Some(b'#'));
+    writer.set_cursor_position(usize::MAX, 0);
+    assert_eq!(writer.get_cursor_position(), (BUFFER_HEIGHT - 1, 0));
+    writer.clear_scroll_region();
+    writer.set_cursor_position(usize::MAX, 0);
+    assert_eq!(writer.get_cursor_position(), (49, 0));
+}
+
+#[test_case]
+fn test_write_at_keeps_cursor() {
+    let mut writer = WRITER.lock();
+    writer.write_string("\nabc");
+    let cursor = writer.get_cursor_position();
+
+    writer.write_at(0, 74, "STATUS");
+    writer.write_at(0, 78, "clipped");
+    writer.write_at(BUFFER_HEIGHT, 0, "ignored");
+
+    assert_eq!(writer.get_cursor_position(), cursor);
+    assert_text_at(&writer, 0, 74, b"STATcl", None);
+}
+
+#[test_case]
+fn test_scrollback_shows_history() {
+    use core::fmt::Write;
+
+    fn assert_row_starts_with(writer: &Writer, row: usize, line: u64) {
+        let text = alloc::format!("line {}", line);
+        for (col, byte) in text.bytes().enumerate() {
+            assert_eq!(glyph_at(writer, row, col), Some(byte));
+        }
+    }
+
+    let mut writer = WRITER.lock();
+    writer.clear_screen();
+    for i in 0..100 {
+        let _ = writeln!(writer, "line {}", i);
+    }
+
+    // 76 lines scrolled off; the 25 rows above the screen are lines 51..=75
+    writer.scroll_up(BUFFER_HEIGHT);
+    assert!(writer.is_viewing_history());
+    for row in 0..BUFFER_HEIGHT {
+        assert_row_starts_with(&writer, row, 51 + row as u64);
+    }
+
+    // Output while scrolled back is held until returning to the bottom
+    let _ = writeln!(writer, "line 100");
+    assert_row_starts_with(&writer, 0, 51);
+
+    writer.scroll_to_bottom();
+    assert!(!writer.is_viewing_history());
+    assert_row_starts_with(&writer, 0, 77);
+    assert_row_starts_with(&writer, BUFFER_HEIGHT - 2, 100);
+}
+
+#[test_case]
+fn test_scrollback_needs_history() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    writer.write_string("old\n");
+    for _ in 0..BUFFER_HEIGHT {
+        writer.write_byte(b'\n');
+    }
+    // Without a history there is nothing to scroll back to
+    writer.scroll_up(1);
+    assert!(!writer.is_viewing_history());
+
+    let mut history = History::new();
+    writer.set_history(Some(&mut history));
+    // 25 rows scroll off, the last of them the one holding "new"
+    writer.write_string("new\n");
+    for _ in 0..BUFFER_HEIGHT - 1 {
+        writer.write_byte(b'\n');
+    }
+    writer.scroll_up(1);
+    assert!(writer.is_viewing_history());
+    assert_eq!(glyph_at(&writer, 0, 0), Some(b'n'));
+
+    let history = writer.take_history();
+    assert!(!writer.is_viewing_history());
+    assert_eq!(history.map(|history| history.len()), Some(BUFFER_HEIGHT));
+}
+
+#[test_case]
+fn test_minimap_shows_density() {
+    let mut buffer = Buffer::blank();
+    let mut history = History::new();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    writer.set_history(Some(&mut history));
+    writer.clear_screen();
+
+    // Lines 0-9 are full, 10-19 blank and 20-49 half full. From the top row,
+    // 25 of the 49 newlines scroll, so lines 0-24 end up in the history.
+    for line in 0..50 {
+        let len = match line {
+            0..=9 => BUFFER_WIDTH,
+            10..=19 => 0,
+            _ => BUFFER_WIDTH / 2,
+        };
+        for _ in 0..len {
+            writer.write_byte(b'x');
+        }
+        if line < 49 {
+            writer.write_byte(b'\n');
+        }
+    }
+
+    // 50 lines over 25 rows: each minimap row summarizes two lines
+    let strip = |writer: &Writer, row| writer.read_cell(row, BUFFER_WIDTH - 1).unwrap();
+    writer.draw_minimap(BUFFER_WIDTH);
+    for (rows, glyph) in [(0..5, 0xb2), (5..10, b' '), (10..25, 0xb1)] {
+        for row in rows {
+            assert_eq!(strip(&writer, row).0, glyph);
+        }
+    }
+    // Lines 25-49 are on screen, which row 12 (lines 24 and 25) reaches into
+    assert_eq!(strip(&writer, 11).1.background(), Color::Black);
+    assert_eq!(strip(&writer, 12).1.background(), Color::Blue);
+    assert_eq!(strip(&writer, 24).1.background(), Color::Blue);
+
+    // Scrolled all the way back, lines 0-24 are on screen
+    writer.scroll_up(BUFFER_HEIGHT);
+    writer.draw_minimap(BUFFER_WIDTH - 1);
+    assert_eq!(strip(&writer, 0).1.background(), Color::Blue);
+    assert_eq!(strip(&writer, 12).1.background(), Color::Blue);
+    assert_eq!(strip(&writer, 13).1.background(), Color::Black);
+
+    // The next flush of a live row paints the text back
+    writer.scroll_to_bottom();
+    assert_eq!(strip(&writer, 24).0, b' ');
+}
+
+#[test_case]
+fn test_flush_on_newline() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let last = BUFFER_HEIGHT - 1;
+    writer.set_auto_flush(false);
+    writer.set_flush_on_newline(true);
+
+    // A partial line stays in the shadow buffer
+    writer.write_string("partial");
+    assert_ne!(glyph_at(&writer, last, 0), Some(b'p'));
+
+    // The newline presents it, scrolled up by one row
+    writer.write_string("\n");
+    assert_eq!(writer.dirty, 0);
+    assert_text_at(&writer, last - 1, 0, b"partial", None);
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            let cell = writer.shadow[row][col];
+            let shown = (cell.ascii_character, cell.color_code);
+            assert_eq!(writer.read_cell(row, col), Some(shown));
+        }
+    }
+}
+
+#[test_case]
+fn test_clear_cycles_per_cell_vs_word_fill() {
+    use crate::serial_println;
+    use core::arch::x86_64::_rdtsc;
+
+    const CLEARS: usize = 1000;
+    let mut writer = WRITER.lock();
+    let blank = [ScreenChar {
+        ascii_character: b' ',
+        color_code: writer.color_code,
+    }; BUFFER_WIDTH];
+
+    // Reference: one 16-bit store per cell
+    // SAFETY: RDTSC is available on every x86_64 CPU and has no side effects.
+    let start = unsafe { _rdtsc() };
+    for _ in 0..CLEARS {
+        for row in 0..BUFFER_HEIGHT {
+            for (col, &cell) in blank.iter().enumerate() {
+                writer.buffer.write(row, col, cell);
+            }
+        }
+    }
+    let per_cell = unsafe { _rdtsc() } - start;
+
+    // Current path: four cells per 64-bit store
+    let start = unsafe { _rdtsc() };
+    for _ in 0..CLEARS {
+        for row in 0..BUFFER_HEIGHT {
+            writer.buffer.write_row(row, &blank);
+        }
+    }
+    let word_fill = unsafe { _rdtsc() } - start;
+
+    serial_println!(
+        "{} clears: {} cycles per cell, {} cycles word fill",
+        CLEARS,
+        per_cell,
+        word_fill
+    );
+    for (col, &cell) in blank.iter().enumerate() {
+        assert_eq!(writer.buffer.read(BUFFER_HEIGHT - 1, col), cell);
+    }
+
+    // Both clears bypassed the shadow buffer, so repaint the screen from it
+    writer.dirty = u64::MAX >> (64 - writer.height);
+    writer.flush();
+}
+
+#[test_case]
+fn test_write_row_matches_per_cell_writes() {
+    let mut buffer = Buffer::blank();
+    // A different character and attribute in every cell
+    let cells: [ScreenChar; BUFFER_WIDTH] = core::array::from_fn(|col| ScreenChar {
+        ascii_character: b'a' + (col % 26) as u8,
+        color_code: ColorCode::from_u8(col as u8),
+    });
+    buffer.write_row(3, &cells);
+    for (col, &cell) in cells.iter().enumerate() {
+        assert_eq!(buffer.read(3, col), cell);
+    }
+    assert_eq!(buffer.read(2, BUFFER_WIDTH - 1).ascii_character, b' ');
+    assert_eq!(buffer.read(4, 0).ascii_character, b' ');
+}
+
+#[test_case]
+fn test_scroll_cycles_mmio_vs_shadow() {
+    use crate::serial_println;
+    use core::arch::x86_64::_rdtsc;
+
+    const SCROLLS: usize = 1000;
+    let mut writer = WRITER.lock();
+
+    // Reference: the previous scroll path, one volatile read and write per cell
+    // SAFETY: RDTSC is available on every x86_64 CPU and has no side effects.
+    let start = unsafe { _rdtsc() };
+    for _ in 0..SCROLLS {
+        for row in 1..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                let cell = writer.buffer.read(row, col);
+                writer.buffer.write(row - 1, col, cell);
+            }
+        }
+    }
+    let mmio = unsafe { _rdtsc() } - start;
+
+    // Current path: shift the shadow buffer, then flush with writes only
+    writer.set_cursor_position(BUFFER_HEIGHT - 1, 0);
+    let start = unsafe { _rdtsc() };
+    for _ in 0..SCROLLS {
+        writer.write_byte(b'\n');
+    }
+    let shadow = unsafe { _rdtsc() } - start;
+
+    serial_println!(
+        "{} scrolls: {} cycles per-cell MMIO, {} cycles shadow + flush",
+        SCROLLS,
+        mmio,
+        shadow
+    );
+    assert_eq!(writer.get_cursor_position(), (BUFFER_HEIGHT - 1, 0));
+}
+
+#[test_case]
+fn test_color_u8_round_trip() {
+    for n in 0..16u8 {
+        let color = Color::from_u8(n).expect("valid color value");
+        assert_eq!(color.as_u8(), n);
+        assert_eq!(Color::try_from(n), Ok(color));
+    }
+    for n in 16..=u8::MAX {
+        assert_eq!(Color::from_u8(n), None);
+        assert_eq!(Color::try_from(n), Err(n));
+    }
+}
+
+#[test_case]
+fn test_color_code_accessors() {
+    for foreground in Color::ALL {
+        for background in &Color::ALL[..8] {
+            let code = ColorCode::new(foreground, *background);
+            assert_eq!(code.foreground(), foreground);
+            assert_eq!(code.background(), *background);
+        }
+    }
+    let bright = ColorCode::new(Color::White, Color::LightBlue);
+    assert_eq!(bright.background(), Color::Blue);
+}
+
+#[test_case]
+fn test_read_cell() {
+    let mut writer = WRITER.lock();
+    let color = ColorCode::new(Color::LightGreen, Color::Blue);
+    writer.write_at_colored(3, 10, "Z", color);
+
+    assert_eq!(writer.read_cell(3, 10), Some((b'Z', color)));
+    assert_eq!(writer.read_cell(BUFFER_HEIGHT, 0), None);
+    assert_eq!(writer.read_cell(0, BUFFER_WIDTH), None);
+}
+
+#[test_case]
+fn test_set_foreground_and_background() {
+    let mut writer = WriterBuilder::new().build();
+    writer.set_color(Color::White, Color::Blue);
+
+    writer.set_foreground(Color::Red);
+    assert_eq!(writer.color_code.foreground(), Color::Red);
+    assert_eq!(writer.color_code.background(), Color::Blue);
+
+    writer.set_background(Color::Green);
+    assert_eq!(writer.color_code.foreground(), Color::Red);
+    assert_eq!(writer.color_code.background(), Color::Green);
+}
+
+#[test_case]
+fn test_lock_free_writes_keep_hardware_cursor() {
+    use core::fmt::Write;
+    use x86_64::instructions::port::Port;
+
+    fn cursor_location() -> u16 {
+        let mut index: Port<u8> = Port::new(CRTC_INDEX);
+        let mut data: Port<u8> = Port::new(CRTC_DATA);
+        // SAFETY: Reading the CRTC cursor location registers has no side
+        // effects.
+        unsafe {
+            index.write(CRTC_CURSOR_LOCATION_HIGH);
+            let high = data.read();
+            index.write(CRTC_CURSOR_LOCATION_LOW);
+            u16::from(high) << 8 | u16::from(data.read())
+        }
+    }
+
+    let mut writer = WRITER.lock();
+    writer.set_cursor_position(7, 11);
+    let before = cursor_location();
+    assert_eq!(before, (7 * BUFFER_WIDTH + 11) as u16);
+
+    let color = ColorCode::new(Color::Red, Color::Black);
+    // SAFETY: WRITER is locked, so nothing else touches the buffer.
+    unsafe {
+        let _ = write!(PanicWriter::new(3, 0, color), "recovered {}", 1);
+        panic_write_string("fault", 4, 0, color);
+    }
+    assert_eq!(cursor_location(), before);
+}
+
+#[test_case]
+fn test_panic_write_string_clamps() {
+    // Hold the lock so nothing else draws while the lock-free write runs
+    let mut writer = WRITER.lock();
+    let color = ColorCode::new(Color::Red, Color::Black);
+    let last = BUFFER_HEIGHT - 1;
+
+    // SAFETY: WRITER is locked, so nothing else touches the buffer.
+    unsafe {
+        panic_write_string("ab☺€", BUFFER_HEIGHT + 5, BUFFER_WIDTH - 3, color);
+        panic_write_string("x\n", 0, 0, color);
+        panic_write_string("never shown", 0, BUFFER_WIDTH, color);
+    }
+    assert_text_at(&writer, last, BUFFER_WIDTH - 3, b"ab\x01", Some(color));
+    assert_eq!(writer.read_cell(0, 1), Some((0xfe, color)));
+
+    // Repaint the rows drawn over behind the writer's back
+    writer.clear_screen();
+}
+
+#[test_case]
+fn test_word_wrap_moves_word() {
+    let mut writer = WRITER.lock();
+    writer.clear_screen();
+    writer.set_word_wrap(true);
+    for _ in 0..76 {
+        writer.write_string("a");
+    }
+    writer.write_string(" hello world");
+    writer.set_word_wrap(false);
+
+    // "hello" would have spanned columns 77-81, so it moved down whole
+    assert_eq!(glyph_at(&writer, 0, 76), Some(b' '));
+    assert_eq!(glyph_at(&writer, 0, 77), Some(b' '));
+    assert_text_at(&writer, 1, 0, b"hello world", None);
+}
+
+#[test_case]
+fn test_draw_box_corners() {
+    let mut writer = WRITER.lock();
+    writer.clear_screen();
+    writer.draw_box(5, 5, 10, 3);
+
+    assert_eq!(glyph_at(&writer, 5, 5), Some(0xda));
+    assert_eq!(glyph_at(&writer, 5, 14), Some(0xbf));
+    assert_eq!(glyph_at(&writer, 7, 5), Some(0xc0));
+    assert_eq!(glyph_at(&writer, 7, 14), Some(0xd9));
+    assert_eq!(glyph_at(&writer, 6, 5), Some(0xb3));
+    assert_eq!(glyph_at(&writer, 5, 6), Some(0xc4));
+}
+
+#[test_case]
+fn test_hex_dump_first_line() {
+    let mut data = [0u8; 32];
+    data[..16].copy_from_slice(b"Hello, world!\n\x00\xff");
+
+    let mut writer = WRITER.lock();
+    writer.clear_screen();
+    let (row, _) = writer.get_cursor_position();
+    writer.hex_dump(&data, 0x1000);
+
+    let expected: &[u8] =
+        b"00001000: 48 65 6C 6C 6F 2C 20 77 6F 72 6C 64 21 0A 00 FF  |Hello, world!...|";
+    assert_text_at(&writer, row, 0, expected, None);
+    assert_eq!(glyph_at(&writer, row + 1, 0), Some(b'0'));
+    assert_eq!(writer.get_cursor_position(), (row + 2, 0));
+}
+
+#[test_case]
+fn test_panic_writer_formats_and_wraps() {
+    use core::fmt::Write;
+
+    // Hold the lock so nothing else draws while the lock-free writer runs
+    let writer = WRITER.lock();
+    let color = ColorCode::new(Color::Red, Color::Black);
+    // SAFETY: WRITER is locked, so nothing else touches the buffer.
+    let mut panic_writer = unsafe { PanicWriter::new(5, 0, color) };
+    let (file, line, message) = ("src/main.rs", 42, "oops");
+    let _ = write!(panic_writer, "PANIC at {}:{}\n{}", file, line, message);
+    assert_text_at(&writer, 5, 0, b"PANIC at src/main.rs:42", Some(color));
+    assert_text_at(&writer, 6, 0, b"oops", Some(color));
+
+    // Text reaching the right edge continues on the next row
+    // SAFETY: WRITER is still locked.
+    let mut panic_writer = unsafe { PanicWriter::new(2, BUFFER_WIDTH - 4, color) };
+    let _ = write!(panic_writer, "index {} out of range", 7);
+    assert_text_at(&writer, 2, BUFFER_WIDTH - 4, b"inde", Some(color));
+    assert_text_at(&writer, 3, 0, b"x 7 out", Some(color));
+}
+
+#[test_case]
+fn test_from_buffer_wraps_and_scrolls() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let last = BUFFER_HEIGHT - 1;
+
+    // 85 characters fill the bottom row, scroll it up and wrap 5 onto a new one
+    for _ in 0..85 {
+        writer.write_byte(b'x');
+    }
+    assert_eq!(glyph_at(&writer, last - 1, BUFFER_WIDTH - 1), Some(b'x'));
+    assert_eq!(glyph_at(&writer, last, 4), Some(b'x'));
+    assert_eq!(glyph_at(&writer, last, 5), Some(b' '));
+
+    writer.write_string("\nab\ncd");
+    assert_eq!(glyph_at(&writer, last - 3, 0), Some(b'x'));
+    assert_eq!(glyph_at(&writer, last - 1, 0), Some(b'a'));
+    assert_eq!(glyph_at(&writer, last, 1), Some(b'd'));
+    assert_eq!(writer.get_cursor_position(), (last, 2));
+}
+
+#[test_case]
+fn test_write_columns_layout() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let last = BUFFER_HEIGHT - 1;
+
+    writer.write_columns(&["alpha", "beta", "gamma"], 2);
+    assert_eq!(glyph_at(&writer, last - 2, 0), Some(b'a'));
+    assert_eq!(glyph_at(&writer, last - 2, 40), Some(b'b'));
+    assert_eq!(glyph_at(&writer, last - 1, 0), Some(b'g'));
+    assert_eq!(writer.get_cursor_position(), (last, 0));
+
+    // 20-cell columns leave room for 19 characters and a separating blank
+    writer.write_columns(&["abcdefghijklmnopqrstuvwxyz", "next"], 4);
+    assert_eq!(glyph_at(&writer, last - 1, 18), Some(b's'));
+    assert_eq!(glyph_at(&writer, last - 1, 19), Some(b' '));
+    assert_eq!(glyph_at(&writer, last - 1, 20), Some(b'n'));
+
+    // Control characters take one cell each instead of moving the cursor
+    writer.write_columns(&["a\nb", "c\td"], 2);
+    assert_text_at(&writer, last - 1, 0, b"a\xfeb ", None);
+    assert_text_at(&writer, last - 1, 40, b"c\xfed ", None);
+    assert_eq!(writer.get_cursor_position(), (last, 0));
+}
+
+#[test_case]
+fn test_tee_writer_reaches_both() {
+    use core::fmt::Write;
+
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let last = BUFFER_HEIGHT - 1;
+
+    // Loopback keeps the text off the test log; it fits the 16-byte
+    // receive FIFO
+    let mut received = [0u8; 6];
+    {
+        let mut serial = SERIAL1.lock();
+        serial.init();
+        serial.set_loopback(true);
+        while serial.receive().is_some() {}
+
+        let _ = write!(TeeWriter::new(&mut writer, &mut serial), "tee {}", 42);
+        for byte in received.iter_mut() {
+            *byte = (0..10_000).find_map(|_| serial.receive()).unwrap_or(0);
+        }
+        serial.set_loopback(false);
+    }
+    assert_eq!(&received, b"tee 42");
+    assert_text_at(&writer, last, 0, b"tee 42", None);
+
+    // An uninitialized port is skipped; the screen still gets the text
+    let mut port = SerialPort::new(crate::serial::COM1);
+    let mut tee = TeeWriter::new(&mut writer, &mut port);
+    assert!(tee.serial.is_none());
+    let _ = tee.write_str("!");
+    assert_eq!(glyph_at(&writer, last, 6), Some(b'!'));
+}
+
+#[test_case]
+fn test_overflow_behaviors() {
+    let last = BUFFER_HEIGHT - 1;
+    // Scroll: the row written before the newline moves up
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    assert_eq!(writer.overflow_behavior(), OverflowBehavior::Scroll);
+    writer.write_string("a\nb");
+    assert_eq!(glyph_at(&writer, last - 1, 0), Some(b'a'));
+    assert_eq!(glyph_at(&writer, last, 0), Some(b'b'));
+
+    // Wrap: output continues on the top row and the bottom row stays put
+    let mut buffer = Buffer::blank();
+    let mut writer = WriterBuilder::new()
+        .auto_scroll(false)
+        .build_with_buffer(&mut buffer);
+    assert_eq!(writer.overflow_behavior(), OverflowBehavior::Wrap);
+    writer.write_string("a\nb");
+    assert_eq!(glyph_at(&writer, last, 0), Some(b'a'));
+    assert_eq!(glyph_at(&writer, 0, 0), Some(b'b'));
+    assert_eq!(writer.get_cursor_position(), (0, 1));
+
+    // Clamp: the last row is cleared and reused
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    writer.set_overflow_behavior(OverflowBehavior::Clamp);
+    writer.write_string("a\nbc");
+    assert_eq!(glyph_at(&writer, last - 1, 0), Some(b' '));
+    assert_eq!(glyph_at(&writer, last, 0), Some(b'b'));
+    assert_eq!(writer.get_cursor_position(), (last, 2));
+    // Text filling past the right edge stays on the last row as well
+    for _ in 0..BUFFER_WIDTH {
+        writer.write_byte(b'x');
+    }
+    assert_eq!(glyph_at(&writer, last - 1, 0), Some(b' '));
+    assert_eq!(writer.get_cursor_position(), (last, 2));
+}
+
+#[test_case]
+fn test_rows_yields_screen_content() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    writer.write_string("hello\nworld");
+
+    let mut hello = [b' '; BUFFER_WIDTH];
+    hello[..5].copy_from_slice(b"hello");
+    let mut world = [b' '; BUFFER_WIDTH];
+    world[..5].copy_from_slice(b"world");
+
+    let rows = writer.rows().skip(BUFFER_HEIGHT - 3);
+    assert!(rows.eq([[b' '; BUFFER_WIDTH], hello, world]));
+}
+
+#[test_case]
+fn test_carriage_return() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let last = BUFFER_HEIGHT - 1;
+
+    // Normally a `\r` only moves back, so the rest of the line stays
+    writer.write_string("Loading 10%\rdone");
+    assert_text_at(&writer, last, 0, b"doneing 10%", None);
+    assert_eq!(writer.get_cursor_position(), (last, 4));
+
+    // In transient mode shorter text leaves nothing of the longer line behind
+    writer.set_transient_cr(true);
+    writer.write_string("\n10%\r20%\r30%");
+    assert_text_at(&writer, last, 0, b"30% ", None);
+    writer.write_string("\nLoading 10%\rdone");
+    assert_text_at(&writer, last, 0, b"done        ", None);
+    assert_eq!(writer.get_cursor_position(), (last, 4));
+
+    // "\r\n" ends the line without clearing it
+    writer.write_string("\r\nnext");
+    assert_eq!(glyph_at(&writer, last - 1, 0), Some(b'd'));
+    assert_eq!(glyph_at(&writer, last, 0), Some(b'n'));
+}
+
+#[test_case]
+fn test_write_string_delayed() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    writer.write_string_delayed("Rust OS", 1_000);
+
+    let row = writer.rows().last().expect("screen has rows");
+    assert_eq!(&row[..8], b"Rust OS ");
+    assert_eq!(writer.get_cursor_position(), (BUFFER_HEIGHT - 1, 7));
+}
+
+#[test_case]
+fn test_status_line_survives_scrolling() {
+    use core::fmt::Write;
+
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    writer.set_status_line("READY");
+    for i in 0..30 {
+        let _ = writeln!(writer, "line {}", i);
+    }
+
+    let status = ColorCode::new(Color::Black, Color::LightGray);
+    assert_text_at(&writer, BUFFER_HEIGHT - 1, 0, b"READY", Some(status));
+    assert_eq!(writer.get_cursor_position(), (BUFFER_HEIGHT - 2, 0));
+    assert_eq!(glyph_at(&writer, BUFFER_HEIGHT - 3, 0), Some(b'l'));
+
+    writer.clear_status_line();
+    writer.write_string("\nlast");
+    assert_eq!(glyph_at(&writer, BUFFER_HEIGHT - 1, 0), Some(b'l'));
+}
+
+#[test_case]
+fn test_write_centered() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    writer.write_centered(3, "0123456789");
+
+    let row = writer.rows().nth(3).expect("row 3 exists");
+    assert_eq!(&row[34..46], b" 0123456789 ");
+
+    // A shorter line leaves nothing of the longer one on either side
+    writer.write_centered(3, "abcd");
+    let row = writer.rows().nth(3).expect("row 3 exists");
+    assert_eq!(&row[38..42], b"abcd");
+    assert!(row.iter().enumerate().all(|(col, &c)| (38..42).contains(&col) || c == b' '));
+}
+
+#[test_case]
+fn test_write_char_translates_to_cp437() {
+    use core::fmt::Write;
+
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let row = BUFFER_HEIGHT - 1;
+    writer.write_char('☺');
+    writer.write_char('é');
+    writer.write_char('€');
+    // Formatted chars take the same path
+    let (frame, unmapped) = ('╔', '中');
+    let _ = write!(writer, "{}{}", frame, unmapped);
+
+    let glyphs: [u8; 5] = core::array::from_fn(|col| writer.shadow[row][col].ascii_character);
+    assert_eq!(glyphs, [0x01, 0x82, 0xfe, 0xc9, 0xfe]);
+
+    // Card suits exist in CP437; emoji and CJK get the fallback glyph, while
+    // control characters stay a block
+    writer.set_fallback_glyph(b'?');
+    let (suit, emoji, cjk) = ('♠', '😀', '中');
+    let _ = write!(writer, "\n{}{}{}", suit, emoji, cjk);
+    writer.write_at(row, 3, "\u{7}");
+    let glyphs: [u8; 4] = core::array::from_fn(|col| writer.shadow[row][col].ascii_character);
+    assert_eq!(glyphs, [0x06, b'?', b'?', 0xfe]);
+}
+
+#[test_case]
+fn test_tab_expands_to_next_stop() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let row = BUFFER_HEIGHT - 1;
+    writer.write_string("a\tb");
+    assert_eq!(glyph_at(&writer, row, 8), Some(b'b'));
+    assert_eq!(glyph_at(&writer, row, 1), Some(b' '));
+
+    writer.set_tab_width(4);
+    writer.write_string("\na\tb");
+    assert_eq!(glyph_at(&writer, row, 4), Some(b'b'));
+
+    // Out-of-range widths are clamped to 1..=16
+    writer.set_tab_width(0);
+    writer.write_string("\na\tb");
+    assert_eq!(glyph_at(&writer, row, 2), Some(b'b'));
+}
+
+#[test_case]
+fn test_measure_width_matches_cursor() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let last = BUFFER_HEIGHT - 1;
+
+    // Plain text, a tab stop counted from the row start, and an accented
+    // character that takes one cell despite being two UTF-8 bytes
+    for (text, start_col, width) in [("hello", 0, 5), ("a\tb", 3, 6), ("café", 0, 4)] {
+        assert_eq!(writer.measure_width(text, start_col), width);
+        writer.set_cursor_position(last, start_col);
+        writer.write_string(text);
+        assert_eq!(writer.get_cursor_position(), (last, start_col + width));
+    }
+}
+
+#[test_case]
+fn test_wrapped_row_count_matches_output() {
+    use alloc::string::String;
+
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    writer.set_word_wrap(true);
+    let last = BUFFER_HEIGHT - 1;
+
+    // Ten tabs fill the row exactly, so the 'x' starts a second one
+    let text = "\t\t\t\t\t\t\t\t\t\tx";
+    assert_eq!(writer.wrapped_row_count(text, BUFFER_WIDTH), 2);
+    writer.write_string(text);
+    assert_eq!(glyph_at(&writer, last, 0), Some(b'x'));
+    assert_eq!(writer.get_cursor_position(), (last, 1));
+
+    // With 3-column stops the 26th tab would end at column 81, so it wraps
+    writer.set_tab_width(3);
+    let mut text = String::from("\nword");
+    for _ in 0..26 {
+        text.push('\t');
+    }
+    text.push_str("end");
+    assert_eq!(writer.wrapped_row_count(&text, BUFFER_WIDTH), 3);
+    writer.write_string(&text);
+    assert_eq!(glyph_at(&writer, last - 1, 0), Some(b'w'));
+    assert_eq!(glyph_at(&writer, last, 0), Some(b'e'));
+
+    assert_eq!(writer.wrapped_row_count("", BUFFER_WIDTH), 0);
+    assert_eq!(writer.wrapped_row_count("hello", BUFFER_WIDTH), 1);
+    assert_eq!(writer.wrapped_row_count("hello world\n", 5), 2);
+    assert_eq!(writer.wrapped_row_count("abcdefghijk", 5), 3);
+    // "one two" / "three" / "four"
+    assert_eq!(writer.wrapped_row_count("one two three four", 8), 3);
+    // Explicit newlines and wrapping add up
+    assert_eq!(writer.wrapped_row_count("first\nsecond\n\nfourth", BUFFER_WIDTH), 4);
+    assert_eq!(writer.wrapped_row_count("one two three\nfour", 8), 3);
+}
+
+#[test_case]
+fn test_blink_sets_attribute_bit_7() {
+    let code = ColorCode::new(Color::Red, Color::Black).with_blink(true);
+    assert_eq!(code.to_u8(), 0x84);
+    assert_eq!(code.with_blink(false).to_u8(), 0x04);
+
+    let mut writer = WriterBuilder::new().build();
+    writer.set_color(Color::Red, Color::Black);
+    writer.set_blink(true);
+    assert_eq!(writer.color_code().to_u8(), 0x84);
+}
+
+#[test_case]
+fn test_blit_row_pads_with_blanks() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let color = ColorCode::new(Color::Yellow, Color::Blue);
+    writer.write_at(3, 0, "stale text that the blit replaces");
+
+    let cells: [ScreenChar; 5] = core::array::from_fn(|i| ScreenChar {
+        ascii_character: b'a' + i as u8,
+        color_code: color,
+    });
+    writer.blit_row(3, &cells);
+    writer.blit_row(BUFFER_HEIGHT, &cells);
+
+    assert_text_at(&writer, 3, 0, b"abcde", Some(color));
+    for col in cells.len()..BUFFER_WIDTH {
+        assert_eq!(writer.read_cell(3, col), Some((b' ', writer.color_code)));
+    }
+}
+
+#[test_case]
+fn test_screen_hash_is_stable() {
+    fn render_banner(buffer: &mut Buffer) -> Writer<'_> {
+        let mut writer = Writer::from_buffer(buffer);
+        writer.clear_screen();
+        writer.set_color(Color::White, Color::Blue);
+        writer.write_centered(0, "Rust OS");
+        writer.set_color(Color::LightGray, Color::Black);
+        writer.write_string("booting...\n");
+        writer
+    }
+
+    let mut buffer = Buffer::blank();
+    let mut writer = render_banner(&mut buffer);
+    let golden = writer.screen_hash();
+    assert_eq!(render_banner(&mut Buffer::blank()).screen_hash(), golden);
+    assert_eq!(writer.screen_hash(), golden);
+
+    // A single glyph, or a single attribute, changes the hash
+    writer.write_at(BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1, "x");
+    let changed = writer.screen_hash();
+    assert_ne!(changed, golden);
+    let red = ColorCode::new(Color::Red, Color::Black);
+    writer.write_at_colored(BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1, "x", red);
+    assert_ne!(writer.screen_hash(), changed);
+}
+
+#[test_case]
+fn test_bar_chart_heights() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let color = ColorCode::new(Color::LightGreen, Color::Black);
+    writer.draw_bar_chart(2, 10, 4, &[0, 50, 100, 13], color);
+
+    // Sum the half-cells of a bar from the glyphs in its column
+    let half_cells = |col| {
+        (2..6)
+            .map(|row| match glyph_at(&writer, row, col) {
+                Some(0xdb) => 2,
+                Some(0xdc) => 1,
+                _ => 0,
+            })
+            .sum::<usize>()
+    };
+    assert_eq!(half_cells(10), 0);
+    assert_eq!(half_cells(11), 4);
+    assert_eq!(half_cells(12), 8);
+    assert_eq!(half_cells(13), 1);
+
+    // Bars grow up from the bottom row of the region
+    assert_eq!(writer.read_cell(5, 11), Some((0xdb, color)));
+    assert_eq!(writer.read_cell(4, 11), Some((0xdb, color)));
+    assert_eq!(writer.read_cell(3, 11), Some((b' ', color)));
+    assert_eq!(writer.read_cell(5, 13), Some((0xdc, color)));
+}
+
+#[test_case]
+fn test_clear_region_blanks_only_region() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let fill = ColorCode::new(Color::White, Color::Magenta);
+    for row in 4..10 {
+        writer.write_at_colored(row, 10, "##########", fill);
+    }
+
+    // Erasing uses the default color, not the active drawing color
+    writer.set_color(Color::Yellow, Color::Red);
+    writer.clear_region(5, 12, 6, 4);
+
+    let blank = (b' ', writer.default_color_code);
+    for row in 4..10 {
+        for col in 10..20 {
+            let expected = if (5..9).contains(&row) && (12..18).contains(&col) {
+                blank
+            } else {
+                (b'#', fill)
+            };
+            assert_eq!(writer.read_cell(row, col), Some(expected));
+        }
+    }
+
+    // Clipped at the screen edges
+    writer.write_at_colored(BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1, "#", fill);
+    writer.clear_region(BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1, 10, 10);
+    assert_eq!(writer.read_cell(BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1), Some(blank));
+}
+
+#[test_case]
+fn test_palette_color_round_trip() {
+    let index = Color::Blue as u8;
+    let original = palette_color(index).expect("valid palette index");
+
+    // Make "Blue" cyan; bits above the 6-bit range are dropped
+    set_palette_color(index, 0, 0xff, 42);
+    assert_eq!(palette_color(index), Some((0, 0x3f, 42)));
+
+    set_palette_color(index, original.0, original.1, original.2);
+    assert_eq!(palette_color(index), Some(original));
+    assert_eq!(palette_color(16), None);
+}
+
+#[test_case]
+fn test_replace_char_and_set_char_color() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let color = ColorCode::new(Color::LightRed, Color::Blue);
+    let highlight = ColorCode::new(Color::Black, Color::Yellow);
+    writer.write_at_colored(2, 5, "abc", color);
+
+    // Each changes only its half of the cell; out-of-range cells are ignored
+    writer.replace_char(2, 5, b'X');
+    writer.replace_char(BUFFER_HEIGHT, 5, b'X');
+    writer.set_char_color(2, 6, highlight);
+    writer.set_char_color(2, BUFFER_WIDTH, highlight);
+    assert_eq!(writer.read_cell(2, 5), Some((b'X', color)));
+    assert_eq!(writer.read_cell(2, 6), Some((b'b', highlight)));
+    assert_eq!(writer.read_cell(2, 7), Some((b'c', color)));
+}
+
+#[test_case]
+fn test_spinner_cycles_frames() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let color = ColorCode::new(Color::Cyan, Color::Black);
+    writer.write_at_colored(0, 0, "[ ]", color);
+    let mut spinner = Spinner::new(0, 1);
+
+    // Stopped spinners draw nothing
+    spinner.tick(&mut writer);
+    assert_eq!(writer.read_cell(0, 1), Some((b' ', color)));
+
+    spinner.start();
+    for frame in *b"|/-\\|" {
+        spinner.tick(&mut writer);
+        assert_eq!(writer.read_cell(0, 1), Some((frame, color)));
+        assert_eq!(writer.read_cell(0, 0), Some((b'[', color)));
+        assert_eq!(writer.read_cell(0, 2), Some((b']', color)));
+    }
+
+    spinner.stop(&mut writer);
+    assert!(!spinner.is_active());
+    assert_eq!(writer.read_cell(0, 1), Some((b' ', color)));
+}
+
+#[test_case]
+fn test_title_survives_scrolling() {
+    use core::fmt::Write;
+
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let title = ColorCode::new(Color::White, Color::Blue);
+    writer.clear_screen();
+    writer.set_title("Rust OS", title);
+    assert_eq!(writer.get_cursor_position(), (1, 0));
+
+    let check_title = |writer: &Writer| {
+        assert_text_at(writer, 0, 35, b" Rust OS ", Some(title));
+        assert_eq!(writer.read_cell(0, 0), Some((b' ', title)));
+    };
+
+    for i in 0..2 * BUFFER_HEIGHT {
+        let _ = writeln!(writer, "line {}", i);
+    }
+    check_title(&writer);
+    assert_eq!(glyph_at(&writer, 1, 0), Some(b'l'));
+
+    writer.clear_screen();
+    check_title(&writer);
+    assert_eq!(writer.get_cursor_position(), (1, 0));
+}
+
+#[test_case]
+fn test_menu_selection_wraps() {
+    let items = ["Boot", "Shell", "Reboot"];
+    let mut menu = Menu::new(2, 4, &items);
+    assert_eq!(menu.selected(), 0);
+
+    menu.down();
+    menu.down();
+    assert_eq!(menu.selected(), 2);
+    menu.down();
+    assert_eq!(menu.selected(), 0);
+    menu.up();
+    assert_eq!(menu.selected(), 2);
+
+    // The selected item is drawn in inverted colors
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let normal = writer.color_code();
+    menu.draw(&mut writer);
+    assert_eq!(glyph_at(&writer, 2, 4), Some(0xda));
+    assert_eq!(writer.read_cell(3, 6), Some((b'B', normal)));
+    assert_eq!(writer.read_cell(5, 6), Some((b'R', normal.inverted())));
+}
+
+#[test_case]
+fn test_write_big_matches_font() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let color = ColorCode::new(Color::Yellow, Color::Black);
+    writer.write_big(1, 2, "HI", color);
+
+    // 'H' spans columns 2-6 and 'I' columns 8-12
+    for (x0, c) in [(2, 'H'), (8, 'I')] {
+        let rows = font::glyph(c).expect("glyph in font");
+        for (dy, bits) in rows.iter().enumerate() {
+            for dx in 0..font::GLYPH_WIDTH {
+                let lit = bits & (1 << (font::GLYPH_WIDTH - 1 - dx)) != 0;
+                let expected = if lit { 0xdb } else { b' ' };
+                assert_eq!(writer.read_cell(1 + dy, x0 + dx), Some((expected, color)));
+            }
+        }
+    }
+    // The crossbar of 'H', the stem of 'I' and the gap between them
+    assert_eq!(glyph_at(&writer, 4, 4), Some(0xdb));
+    assert_eq!(glyph_at(&writer, 1, 4), Some(b' '));
+    assert_eq!(glyph_at(&writer, 3, 10), Some(0xdb));
+    assert_eq!(glyph_at(&writer, 3, 7), Some(b' '));
+}
+
+#[test_case]
+fn test_line_fill_colors_whole_row() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let last = BUFFER_HEIGHT - 1;
+    let blue = ColorCode::new(Color::White, Color::Blue);
+
+    // Without line fill the trailing cells keep their old color
+    writer.set_color(Color::White, Color::Blue);
+    writer.write_string("plain");
+    assert_ne!(writer.read_cell(last, 5).map(|(_, c)| c), Some(blue));
+
+    writer.set_line_fill(true);
+    writer.write_string("\nshort");
+    assert_eq!(writer.read_cell(last, 0), Some((b's', blue)));
+    for col in 5..BUFFER_WIDTH {
+        assert_eq!(writer.read_cell(last, col), Some((b' ', blue)));
+    }
+
+    // A carriage return only moves back, so a partial overwrite keeps the rest
+    writer.write_string("\nLoading 10%\r20");
+    assert_text_at(&writer, last, 0, b"20ading 10%", Some(blue));
+}
+
+#[test_case]
+fn test_clear_to_end_and_start_of_screen() {
+    fn fill(writer: &mut Writer) {
+        let line = [b'x'; BUFFER_WIDTH];
+        let line = core::str::from_utf8(&line).unwrap_or_default();
+        for row in 0..BUFFER_HEIGHT {
+            writer.write_at(row, 0, line);
+        }
+        writer.set_cursor_position(10, 40);
+    }
+
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    fill(&mut writer);
+    writer.clear_to_end_of_screen();
+    assert_eq!(glyph_at(&writer, 9, BUFFER_WIDTH - 1), Some(b'x'));
+    assert_eq!(glyph_at(&writer, 10, 39), Some(b'x'));
+    assert_eq!(glyph_at(&writer, 10, 40), Some(b' '));
+    assert_eq!(glyph_at(&writer, 11, 0), Some(b' '));
+    assert_eq!(glyph_at(&writer, BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1), Some(b' '));
+    assert_eq!(writer.get_cursor_position(), (10, 40));
+
+    fill(&mut writer);
+    writer.clear_to_start_of_screen();
+    assert_eq!(glyph_at(&writer, 0, 0), Some(b' '));
+    assert_eq!(glyph_at(&writer, 9, BUFFER_WIDTH - 1), Some(b' '));
+    assert_eq!(glyph_at(&writer, 10, 40), Some(b' '));
+    assert_eq!(glyph_at(&writer, 10, 41), Some(b'x'));
+    assert_eq!(glyph_at(&writer, 11, 0), Some(b'x'));
+    assert_eq!(writer.get_cursor_position(), (10, 40));
+}
+
+#[test_case]
+fn test_draw_test_pattern_bars() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    writer.draw_test_pattern();
+    let color_at = |row, col| writer.read_cell(row, col).map(|(_, c)| c);
+
+    // Sample the middle of each 10-column bar on a few rows
+    for (bar, &color) in Color::ALL[..8].iter().enumerate() {
+        for row in [0, BUFFER_HEIGHT / 2, BUFFER_HEIGHT - 2] {
+            assert_eq!(color_at(row, bar * 10 + 5).map(|c| c.background()), Some(color));
+        }
+    }
+    // The last row shows all 16 colors, five columns each
+    for (swatch, &color) in Color::ALL.iter().enumerate() {
+        let code = color_at(BUFFER_HEIGHT - 1, swatch * 5 + 2);
+        assert_eq!(code, Some(ColorCode::new(color, Color::Black)));
+    }
+}
+
+#[test_case]
+fn test_write_markdown_colors() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    writer.set_color(Color::Cyan, Color::Black);
+    let normal = writer.color_code();
+    let last = BUFFER_HEIGHT - 1;
+
+    writer.write_markdown("# Help\nuse **ls** or `cat`\n**open");
+
+    // Heading, without its marker
+    let heading = ColorCode::new(Color::White, Color::Black);
+    assert_text_at(&writer, last - 2, 0, b"Help", Some(heading));
+
+    // Bold and code spans, surrounded by normal text
+    let bold = ColorCode::new(Color::LightCyan, Color::Black);
+    let code = ColorCode::new(Color::Black, Color::LightGray);
+    assert_text_at(&writer, last - 1, 0, b"use ", Some(normal));
+    assert_text_at(&writer, last - 1, 4, b"ls", Some(bold));
+    assert_text_at(&writer, last - 1, 6, b" or ", Some(normal));
+    assert_text_at(&writer, last - 1, 10, b"cat", Some(code));
+
+    // An unclosed marker is literal, and the color is restored
+    assert_text_at(&writer, last, 0, b"**open", Some(normal));
+    assert_eq!(writer.color_code(), normal);
+}
+
+#[test_case]
+fn test_overlay_restores_background() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let color = ColorCode::new(Color::LightGreen, Color::Black);
+    for row in 4..9 {
+        writer.write_at_colored(row, 0, "background text under the overlay", color);
+    }
+    let background = writer.screen_hash();
+    let star = ColorCode::new(Color::Yellow, Color::Red);
+
+    {
+        let mut overlay = Overlay::new(&mut writer, 5, 2, 6, 3);
+        overlay.frame(|writer| writer.write_at_colored(5, 3, "*", star));
+        assert_eq!(overlay.writer.read_cell(5, 3), Some((b'*', star)));
+
+        // The next frame starts from the background, not the previous frame
+        overlay.frame(|writer| writer.write_at_colored(6, 4, "*", star));
+        assert_eq!(overlay.writer.read_cell(5, 3), Some((b'k', color)));
+        assert_eq!(overlay.writer.read_cell(6, 4), Some((b'*', star)));
+    }
+
+    assert_eq!(writer.read_cell(6, 4), Some((b'g', color)));
+    assert_eq!(writer.screen_hash(), background);
+}
+
+#[test_case]
+fn test_backspace_erases_previous_char() {
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    let last = BUFFER_HEIGHT - 1;
+    writer.write_string("ab");
+    writer.write_byte(BACKSPACE);
+    writer.write_string("c");
+
+    let row = writer.rows().last().expect("screen has rows");
+    assert_eq!(&row[..3], b"ac ");
+    assert_eq!(writer.get_cursor_position(), (last, 2));
+
+    // At column 0 there is nothing to erase
+    writer.write_string("\n\x08x");
+    assert_eq!(glyph_at(&writer, last, 0), Some(b'x'));
+    assert_eq!(glyph_at(&writer, last - 1, 1), Some(b'c'));
+}
+
+#[test_case]
+fn test_clear_screen_blanks_every_cell() {
+    use core::fmt::Write;
+
+    let mut buffer = Buffer::blank();
+    let mut writer = Writer::from_buffer(&mut buffer);
+    for i in 0..BUFFER_HEIGHT + 5 {
+        let _ = writeln!(writer, "text that fills the screen {}", i);
+    }
+    writer.write_at(0, BUFFER_WIDTH - 3, "end");
+
+    writer.clear_screen();
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            assert_eq!(glyph_at(&writer, row, col), Some(b' '));
+        }
+    }
+    assert_eq!(writer.get_cursor_position(), (0, 0));
+}
@@ -0,0 +1,281 @@
+use x86_64::instructions::port::{Port, PortReadOnly};
+
+use crate::vga_buffer::{
+    ATTRIBUTE_CONTROLLER_INDEX, ATTRIBUTE_CONTROLLER_READ, ATTRIBUTE_PAS, CRTC_DATA, CRTC_INDEX,
+    INPUT_STATUS_1,
+};
+
+const MISC_OUTPUT_READ: u16 = 0x3cc;
+const MISC_OUTPUT_WRITE: u16 = 0x3c2;
+const SEQUENCER_INDEX: u16 = 0x3c4;
+const SEQUENCER_DATA: u16 = 0x3c5;
+const GRAPHICS_INDEX: u16 = 0x3ce;
+const GRAPHICS_DATA: u16 = 0x3cf;
+
+const SEQUENCER_REGISTERS: usize = 5;
+const CRTC_REGISTERS: usize = 25;
+const GRAPHICS_REGISTERS: usize = 9;
+const ATTRIBUTE_REGISTERS: usize = 21;
+
+/// CRTC vertical retrace end register; bit 7 write-protects CRTC registers 0-7.
+const CRTC_VERTICAL_RETRACE_END: u8 = 0x11;
+const CRTC_PROTECT_BIT: u8 = 0x80;
+
+/// A snapshot of the VGA controller registers that define the current mode.
+///
+/// Covers the miscellaneous output register plus the sequencer, CRTC,
+/// graphics controller and attribute controller register files. The DAC
+/// palette and font memory are not included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VgaState {
+    pub misc_output: u8,
+    pub sequencer: [u8; SEQUENCER_REGISTERS],
+    pub crtc: [u8; CRTC_REGISTERS],
+    pub graphics: [u8; GRAPHICS_REGISTERS],
+    pub attribute: [u8; ATTRIBUTE_REGISTERS],
+}
+
+/// Reads an indexed register through an index/data port pair.
+///
+/// # Safety
+///
+/// The ports must be a valid VGA index/data pair and `index` must be a
+/// register that exists behind it.
+unsafe fn read_indexed(index_port: u16, data_port: u16, index: u8) -> u8 {
+    Port::<u8>::new(index_port).write(index);
+    Port::<u8>::new(data_port).read()
+}
+
+/// Writes an indexed register through an index/data port pair.
+///
+/// # Safety
+///
+/// Same requirements as `read_indexed`; additionally the value must be valid
+/// for the register, since a bad CRTC or sequencer value can hang the display.
+unsafe fn write_indexed(index_port: u16, data_port: u16, index: u8, value: u8) {
+    Port::<u8>::new(index_port).write(index);
+    Port::<u8>::new(data_port).write(value);
+}
+
+/// Captures the current VGA register state.
+///
+/// Use this before switching modes or reprogramming timing so the original
+/// mode can be restored with `restore_state`.
+pub fn save_state() -> VgaState {
+    let mut state = VgaState {
+        misc_output: 0,
+        sequencer: [0; SEQUENCER_REGISTERS],
+        crtc: [0; CRTC_REGISTERS],
+        graphics: [0; GRAPHICS_REGISTERS],
+        attribute: [0; ATTRIBUTE_REGISTERS],
+    };
+
+    // SAFETY: All ports are the standard VGA register ports, and every index
+    // read lies within the documented register file. Reads have no side
+    // effects except on the attribute controller flip-flop, which is reset via
+    // 0x3DA before each access and left with PAS set so the display stays on.
+    unsafe {
+        state.misc_output = PortReadOnly::<u8>::new(MISC_OUTPUT_READ).read();
+
+        for (i, reg) in state.sequencer.iter_mut().enumerate() {
+            *reg = read_indexed(SEQUENCER_INDEX, SEQUENCER_DATA, i as u8);
+        }
+        for (i, reg) in state.crtc.iter_mut().enumerate() {
+            *reg = read_indexed(CRTC_INDEX, CRTC_DATA, i as u8);
+        }
+        for (i, reg) in state.graphics.iter_mut().enumerate() {
+            *reg = read_indexed(GRAPHICS_INDEX, GRAPHICS_DATA, i as u8);
+        }
+
+        let mut status = PortReadOnly::<u8>::new(INPUT_STATUS_1);
+        let mut ac_index = Port::<u8>::new(ATTRIBUTE_CONTROLLER_INDEX);
+        let mut ac_read = PortReadOnly::<u8>::new(ATTRIBUTE_CONTROLLER_READ);
+        for (i, reg) in state.attribute.iter_mut().enumerate() {
+            status.read();
+            ac_index.write(i as u8);
+            *reg = ac_read.read();
+        }
+        status.read();
+        ac_index.write(ATTRIBUTE_PAS);
+    }
+
+    state
+}
+
+/// Restores a register state previously captured with `save_state`.
+///
+/// The sequencer is held in synchronous reset while it is reprogrammed, and
+/// the CRTC write-protect bit is cleared before the CRTC registers are written
+/// (the saved value of register 0x11 re-applies it at the end).
+pub fn restore_state(state: &VgaState) {
+    // SAFETY: The values come from a `VgaState` read from the same hardware, so
+    // they describe a mode the adapter was already displaying. The write order
+    // follows the standard VGA reprogramming sequence.
+    unsafe {
+        Port::<u8>::new(MISC_OUTPUT_WRITE).write(state.misc_output);
+
+        // Synchronous reset while the clocking registers change
+        write_indexed(SEQUENCER_INDEX, SEQUENCER_DATA, 0, 0x01);
+        for (i, &reg) in state.sequencer.iter().enumerate().skip(1) {
+            write_indexed(SEQUENCER_INDEX, SEQUENCER_DATA, i as u8, reg);
+        }
+        write_indexed(SEQUENCER_INDEX, SEQUENCER_DATA, 0, state.sequencer[0]);
+
+        // Unlock CRTC registers 0-7 before writing them
+        let retrace_end = read_indexed(CRTC_INDEX, CRTC_DATA, CRTC_VERTICAL_RETRACE_END);
+        write_indexed(
+            CRTC_INDEX,
+            CRTC_DATA,
+            CRTC_VERTICAL_RETRACE_END,
+            retrace_end & !CRTC_PROTECT_BIT,
+        );
+        for (i, &reg) in state.crtc.iter().enumerate() {
+            write_indexed(CRTC_INDEX, CRTC_DATA, i as u8, reg);
+        }
+
+        for (i, &reg) in state.graphics.iter().enumerate() {
+            write_indexed(GRAPHICS_INDEX, GRAPHICS_DATA, i as u8, reg);
+        }
+
+        // The attribute controller takes index and data on the same port,
+        // alternating via its flip-flop, which reading 0x3DA resets.
+        let mut status = PortReadOnly::<u8>::new(INPUT_STATUS_1);
+        let mut ac = Port::<u8>::new(ATTRIBUTE_CONTROLLER_INDEX);
+        for (i, &reg) in state.attribute.iter().enumerate() {
+            status.read();
+            ac.write(i as u8);
+            ac.write(reg);
+        }
+        status.read();
+        ac.write(ATTRIBUTE_PAS);
+    }
+}
+
+/// Plane 2 of VGA memory, where the font lives, when mapped for font access.
+const FONT_PLANE: usize = 0xa0000;
+/// Each of the 256 glyphs in a font block occupies 32 bytes of plane 2.
+const GLYPH_STRIDE: usize = 32;
+/// Offset of font block 1 in plane 2; block 0 holds the BIOS 8x16 font.
+const FONT_BLOCK_1: usize = 0x4000;
+
+/// Sequencer character map select values for font block 0 and block 1.
+const CHARACTER_MAP_BLOCK_0: u8 = 0x00;
+const CHARACTER_MAP_BLOCK_1: u8 = 0x05;
+
+/// CRTC registers defining the character cell height and cursor shape.
+const CRTC_MAXIMUM_SCAN_LINE: u8 = 0x09;
+const CRTC_CURSOR_START: u8 = 0x0a;
+const CRTC_CURSOR_END: u8 = 0x0b;
+/// Low five bits of the three registers above hold a scan line number.
+const SCAN_LINE_MASK: u8 = 0x1f;
+
+/// The color text modes the writer supports. Both are 80 columns wide and
+/// use the same 400-scan-line timing; they differ only in character height.
+// Only chosen through `Writer::set_text_mode`, which kernel_main does not call
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextMode {
+    /// The BIOS default: 8x16 characters, 25 rows.
+    Mode80x25,
+    /// 8x8 characters, 50 rows.
+    Mode80x50,
+}
+
+impl TextMode {
+    /// Number of text rows in this mode.
+    pub const fn rows(self) -> usize {
+        match self {
+            TextMode::Mode80x25 => 25,
+            TextMode::Mode80x50 => 50,
+        }
+    }
+
+    /// Height of one character cell in scan lines.
+    const fn cell_height(self) -> u8 {
+        match self {
+            TextMode::Mode80x25 => 16,
+            TextMode::Mode80x50 => 8,
+        }
+    }
+}
+
+/// Reprograms the VGA for `mode`.
+///
+/// Switching to 80x50 builds an 8x8 font in font block 1 by taking every other
+/// scan line of the BIOS 8x16 font in block 0, then selects block 1 and
+/// halves the character cell height. Block 0 is never modified, so switching
+/// back to 80x25 only has to select it again. Screen contents are not
+/// preserved; callers normally go through `Writer::set_text_mode`, which also
+/// resizes and clears the writer.
+pub fn set_text_mode(mode: TextMode) {
+    let character_map = match mode {
+        TextMode::Mode80x25 => CHARACTER_MAP_BLOCK_0,
+        TextMode::Mode80x50 => {
+            build_8x8_font();
+            CHARACTER_MAP_BLOCK_1
+        }
+    };
+
+    let last_line = mode.cell_height() - 1;
+    // SAFETY: All ports are the standard VGA register ports. Only the scan
+    // line fields of the cell height and cursor shape registers and the
+    // character map select change, none of which affect display timing.
+    unsafe {
+        write_indexed(SEQUENCER_INDEX, SEQUENCER_DATA, 3, character_map);
+        for (register, line) in [
+            (CRTC_MAXIMUM_SCAN_LINE, last_line),
+            (CRTC_CURSOR_START, last_line - 1),
+            (CRTC_CURSOR_END, last_line),
+        ] {
+            let value = read_indexed(CRTC_INDEX, CRTC_DATA, register);
+            write_indexed(CRTC_INDEX, CRTC_DATA, register, (value & !SCAN_LINE_MASK) | line);
+        }
+    }
+}
+
+/// Copies every other scan line of each glyph in font block 0 to block 1.
+fn build_8x8_font() {
+    let saved = save_state();
+
+    // SAFETY: The sequencer and graphics controller are switched to the
+    // standard planar configuration for font access: writes and reads go to
+    // plane 2 only, without odd/even addressing, mapped at 0xA0000. In that
+    // configuration 0xA0000..0xB0000 is plane 2, so every access below lies in
+    // font memory. The previous register state is restored afterwards.
+    unsafe {
+        write_indexed(SEQUENCER_INDEX, SEQUENCER_DATA, 2, 0x04);
+        write_indexed(SEQUENCER_INDEX, SEQUENCER_DATA, 4, 0x07);
+        write_indexed(GRAPHICS_INDEX, GRAPHICS_DATA, 4, 0x02);
+        write_indexed(GRAPHICS_INDEX, GRAPHICS_DATA, 5, 0x00);
+        write_indexed(GRAPHICS_INDEX, GRAPHICS_DATA, 6, 0x04);
+
+        let plane = FONT_PLANE as *mut u8;
+        for glyph in 0..256 {
+            let source = plane.add(glyph * GLYPH_STRIDE);
+            let target = plane.add(FONT_BLOCK_1 + glyph * GLYPH_STRIDE);
+            for line in 0..8 {
+                let bits = core::ptr::read_volatile(source.add(line * 2));
+                core::ptr::write_volatile(target.add(line), bits);
+            }
+        }
+    }
+
+    restore_state(&saved);
+}
+
+#[test_case]
+fn test_restore_state_reverts_crtc() {
+    let saved = save_state();
+    let cursor_start = saved.crtc[CRTC_CURSOR_START as usize];
+
+    // Bit 5 of the cursor start register hides the cursor; harmless to flip
+    // SAFETY: CRTC_CURSOR_START is a valid CRTC register outside the
+    // write-protected range, and any value only affects the cursor shape.
+    unsafe {
+        write_indexed(CRTC_INDEX, CRTC_DATA, CRTC_CURSOR_START, cursor_start ^ 0x20);
+    }
+    assert_eq!(save_state().crtc[CRTC_CURSOR_START as usize], cursor_start ^ 0x20);
+
+    restore_state(&saved);
+    assert_eq!(save_state().crtc[CRTC_CURSOR_START as usize], cursor_start);
+}
@@ -0,0 +1,97 @@
+//! Panics with a message that needs escaping and checks the JSON report.
+//!
+//! The report is only written by a panic handler, so this runs as its own
+//! kernel without the test harness. Its panic handler formats the report
+//! into a stack buffer, compares it with the expected line and exits QEMU
+//! with the result.
+
+#![no_std]
+#![no_main]
+// The shared modules below contain `#[test_case]` tests; this lets them
+// compile here, where they are simply left out
+#![feature(custom_test_frameworks)]
+
+use bootloader::{BootInfo, entry_point};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+#[path = "../src/json_panic.rs"]
+mod json_panic;
+#[path = "../src/qemu.rs"]
+mod qemu;
+#[path = "../src/serial.rs"]
+#[allow(dead_code)]
+mod serial;
+
+use qemu::{QemuExitCode, exit_qemu};
+
+/// Line of the `panic!` below, recorded just before it runs.
+static PANIC_LINE: AtomicU32 = AtomicU32::new(0);
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    serial_print!("json_panic::json_panic...\t");
+
+    PANIC_LINE.store(line!() + 1, Ordering::Relaxed);
+    panic!("say \"hi\"\nbye");
+}
+
+/// Collects output in a fixed stack buffer.
+struct LineBuffer {
+    bytes: [u8; 256],
+    len: usize,
+}
+
+impl LineBuffer {
+    const fn new() -> LineBuffer {
+        LineBuffer {
+            bytes: [0; 256],
+            len: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl Write for LineBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.bytes.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut report = LineBuffer::new();
+    let mut expected = LineBuffer::new();
+    let _ = json_panic::write_panic_json(&mut report, info);
+    // `panic!` is indented by four spaces, so it starts in column 5
+    let _ = writeln!(
+        expected,
+        r#"{{"panic":true,"file":"{}","line":{},"col":5,"msg":"say \"hi\"\nbye"}}"#,
+        file!(),
+        PANIC_LINE.load(Ordering::Relaxed)
+    );
+
+    // One line, ending in the only newline
+    let single_line = report.as_bytes().iter().filter(|&&byte| byte == b'\n').count() == 1;
+    if single_line && report.as_bytes() == expected.as_bytes() {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\n");
+        if let Ok(report) = core::str::from_utf8(report.as_bytes()) {
+            serial_print!("Report: {}", report);
+        }
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {
+        core::hint::spin_loop();
+    }
+}
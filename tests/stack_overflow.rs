@@ -0,0 +1,81 @@
+//! Overflows the kernel stack and checks that the double fault is caught.
+//!
+//! A double fault cannot be resumed, so this runs as its own kernel without
+//! the test harness. Its double-fault handler reports success and exits QEMU;
+//! without the IST stack from `gdt` the CPU would triple-fault and reboot
+//! instead, and the run would time out.
+
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+// The shared modules below contain `#[test_case]` tests; this lets them
+// compile here, where they are simply left out
+#![feature(custom_test_frameworks)]
+
+use bootloader::{BootInfo, entry_point};
+use core::panic::PanicInfo;
+use spin::Lazy;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+#[path = "../src/gdt.rs"]
+#[allow(dead_code)]
+mod gdt;
+#[path = "../src/qemu.rs"]
+mod qemu;
+#[path = "../src/serial.rs"]
+#[allow(dead_code)]
+mod serial;
+
+use qemu::{QemuExitCode, exit_qemu};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    serial_print!("stack_overflow::stack_overflow...\t");
+
+    gdt::init();
+    TEST_IDT.load();
+
+    stack_overflow();
+
+    panic!("Execution continued after stack overflow");
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow();
+    // Keeps the recursion from being turned into a loop
+    core::hint::black_box(());
+}
+
+static TEST_IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
+    let mut idt = InterruptDescriptorTable::new();
+    // SAFETY: The index refers to the double-fault stack set up by gdt::init.
+    unsafe {
+        idt.double_fault
+            .set_handler_fn(test_double_fault_handler)
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+    }
+    idt
+});
+
+extern "x86-interrupt" fn test_double_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    _error_code: u64,
+) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
+    exit_qemu(QemuExitCode::Failed);
+    loop {
+        core::hint::spin_loop();
+    }
+}